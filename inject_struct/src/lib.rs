@@ -3,15 +3,20 @@ extern crate syn;
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{DeriveInput, parse_macro_input, Type};
+use shared::validation::Rule;
+use syn::{Attribute, DeriveInput, Expr, ExprLit, Lit, LitStr, parse_macro_input, Type};
 
 // Thanks, Mr. ChatGpt for making this clean code 😚😚😚
-#[proc_macro_derive(InjectStruct)]
+#[proc_macro_derive(InjectStruct, attributes(validate))]
 pub fn inject_macro_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
     // Extract the Annotated Type Name
     let name = input.ident;
+    let rename_all = match serde_attribute(&input.attrs, "rename_all") {
+        Ok(rename_all) => rename_all,
+        Err(err) => return TokenStream::from(err.to_compile_error())
+    };
 
     // Extract Annotated Type Fields and Panic if it is not a Struct
     let fields = match input.data {
@@ -221,6 +226,61 @@ pub fn inject_macro_derive(input: TokenStream) -> TokenStream {
         }
     });
 
+    // Rules of `#[validate(min = 18, len = "2..50", email)]` attributes, checked at compile time
+    let mut rules = Vec::new();
+    let mut checks = Vec::new();
+
+    for field in fields.iter() {
+        let field_name = field.ident.as_ref().expect("Expected a named field");
+        let name_str = field_name.to_string();
+        let mut field_rules = Vec::new();
+
+        for attribute in field.attrs.iter().filter(|attribute| attribute.path().is_ident("validate")) {
+            let parsed = attribute.parse_nested_meta(|meta| {
+                let rule_name = meta.path.get_ident().map(|ident| ident.to_string()).unwrap_or_default();
+
+                let rule = if meta.input.peek(syn::Token![=]) {
+                    let value = match meta.value()?.parse::<Lit>()? {
+                        Lit::Str(value) => value.value(),
+                        Lit::Int(value) => value.base10_digits().to_string(),
+                        Lit::Float(value) => value.base10_digits().to_string(),
+                        _ => return Err(meta.error("Expected a string or a number"))
+                    };
+
+                    format!("{}={}", rule_name, value)
+                } else {
+                    rule_name
+                };
+
+                Rule::parse(&rule).map_err(|err| meta.error(err))?;
+                field_rules.push(rule);
+
+                Ok(())
+            });
+
+            if let Err(err) = parsed {
+                return TokenStream::from(err.to_compile_error());
+            }
+        }
+
+        if !field_rules.is_empty() {
+            let serde_name = match serde_attribute(&field.attrs, "rename") {
+                Ok(rename) => rename.unwrap_or(rename_field(&name_str, rename_all.as_deref())),
+                Err(err) => return TokenStream::from(err.to_compile_error())
+            };
+            let index = rules.len();
+
+            rules.push(quote! {
+                (#name_str, Vec::from([#(shared::validation::Rule::parse(#field_rules).unwrap()),*]))
+            });
+            checks.push(quote! {
+                if let Err(err) = shared::validation::Rule::check_value(&rules[#index].1, #serde_name, &self.#field_name) {
+                    errors.insert(String::from(#serde_name), err);
+                }
+            });
+        }
+    }
+
     // Generate the `from_hashmap` function implementation
     let implementation = quote! {
         impl shared::wrust_traits::InjectStructTrait for #name {
@@ -234,8 +294,75 @@ pub fn inject_macro_derive(input: TokenStream) -> TokenStream {
                     #(#hashmap_initializations),*
                 }
             }
+            fn rules() -> &'static [(&'static str, Vec<shared::validation::Rule>)] {
+                static RULES: std::sync::OnceLock<Vec<(&'static str, Vec<shared::validation::Rule>)>> = std::sync::OnceLock::new();
+
+                RULES.get_or_init(|| Vec::from([#(#rules),*]))
+            }
+            fn check_rules(&self) -> shared::error::ErrorsHashMap {
+                let rules = <Self as shared::wrust_traits::InjectStructTrait>::rules();
+                let mut errors = shared::error::ErrorsHashMap::new();
+
+                #(#checks)*
+
+                errors
+            }
         }
     };
 
     TokenStream::from(implementation)
-}
\ No newline at end of file
+}
+
+// The value of a `#[serde(key = "value")]` or `#[serde(key(deserialize = "value"))]` attribute
+fn serde_attribute(attributes: &[Attribute], key: &str) -> syn::Result<Option<String>> {
+    let mut result = None;
+
+    for attribute in attributes.iter().filter(|attribute| attribute.path().is_ident("serde")) {
+        attribute.parse_nested_meta(|meta| {
+            let is_key = meta.path.is_ident(key);
+
+            if meta.input.peek(syn::Token![=]) {
+                let value = meta.value()?.parse::<Expr>()?;
+
+                if let (true, Expr::Lit(ExprLit { lit: Lit::Str(value), .. })) = (is_key, value) {
+                    result = Some(value.value());
+                }
+            } else if meta.input.peek(syn::token::Paren) {
+                meta.parse_nested_meta(|nested| {
+                    let value = nested.value()?.parse::<LitStr>()?;
+
+                    if is_key && nested.path.is_ident("deserialize") {
+                        result = Some(value.value());
+                    }
+
+                    Ok(())
+                })?;
+            }
+
+            Ok(())
+        })?;
+    }
+
+    Ok(result)
+}
+
+// The name of a field once the `rename_all` rule of its struct is applied, like serde does
+fn rename_field(field: &str, rename_all: Option<&str>) -> String {
+    let pascal_case = || field.split('_').map(|word| {
+        let mut chars = word.chars();
+        chars.next().map_or(String::new(), |first| first.to_ascii_uppercase().to_string() + chars.as_str())
+    }).collect::<String>();
+
+    match rename_all {
+        Some("UPPERCASE") | Some("SCREAMING_SNAKE_CASE") => field.to_ascii_uppercase(),
+        Some("PascalCase") => pascal_case(),
+        Some("camelCase") => {
+            let pascal_case = pascal_case();
+            let mut chars = pascal_case.chars();
+            chars.next().map_or(String::new(), |first| first.to_ascii_lowercase().to_string() + chars.as_str())
+        },
+        Some("kebab-case") => field.replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => field.to_ascii_uppercase().replace('_', "-"),
+        _ => field.to_string()
+    }
+}
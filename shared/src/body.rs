@@ -0,0 +1,134 @@
+use std::io::{self, BufRead, Read};
+use crate::error::ReadError;
+
+// Longest chunk size or trailer line accepted, a line that never ends must not grow forever
+const MAX_LINE_LENGTH: usize = 8 * 1024;
+
+/// Reads the body of a request as it arrives, decoding the chunked transfer encoding
+/// and refusing to go past `max_body_size` bytes.
+/// Its errors carry the `ReadError` they stand for, see `From<io::Error> for ReadError`.
+pub struct BodyReader<'a, R: BufRead> {
+    source: &'a mut R,
+    is_chunked: bool,
+    // Bytes left in the body, or in the current chunk
+    remaining: usize,
+    total: usize,
+    max_body_size: usize,
+    is_done: bool
+}
+
+impl<'a, R: BufRead> BodyReader<'a, R> {
+    /// A body of `Content-Length` bytes, refused without being read when it is too large
+    pub fn sized(source: &'a mut R, content_length: usize, max_body_size: usize) -> Result<Self, ReadError> {
+        if content_length > max_body_size {
+            return Err(ReadError::TooLarge(max_body_size));
+        }
+
+        Ok(BodyReader {
+            source,
+            is_chunked: false,
+            remaining: content_length,
+            total: content_length,
+            max_body_size,
+            is_done: content_length == 0
+        })
+    }
+
+    pub fn chunked(source: &'a mut R, max_body_size: usize) -> Self {
+        BodyReader {
+            source,
+            is_chunked: true,
+            remaining: 0,
+            total: 0,
+            max_body_size,
+            is_done: false
+        }
+    }
+
+    // Chunks are `<hex size>[;extensions]\r\n<data>\r\n`, ending with a zero sized chunk and optional trailers
+    fn start_chunk(&mut self) -> io::Result<()> {
+        let size_line = self.read_line("Invalid Chunk Size")?;
+
+        let size = size_line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| Self::invalid("Invalid Chunk Size"))?;
+
+        if size == 0 {
+            self.is_done = true;
+            return self.skip_trailers();
+        }
+
+        self.total = match self.total.checked_add(size) {
+            Some(total) if total <= self.max_body_size => total,
+            _ => return Err(io::Error::other(ReadError::TooLarge(self.max_body_size)))
+        };
+        self.remaining = size;
+
+        Ok(())
+    }
+
+    fn end_chunk(&mut self) -> io::Result<()> {
+        let mut line_end = [0; 2];
+
+        match self.source.read_exact(&mut line_end) {
+            Ok(_) if &line_end == b"\r\n" => Ok(()),
+            _ => Err(Self::invalid("Incomplete Request Body"))
+        }
+    }
+
+    // Trailers are not used, they are read till the empty line ending the body
+    fn skip_trailers(&mut self) -> io::Result<()> {
+        loop {
+            // The end of the stream is an empty line too
+            if self.read_line("Incomplete Request Body")?.trim().is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn read_line(&mut self, message: &str) -> io::Result<String> {
+        let mut line = String::new();
+        let read = (&mut *self.source).take(MAX_LINE_LENGTH as u64).read_line(&mut line).map_err(|_| Self::invalid(message))?;
+
+        if read == MAX_LINE_LENGTH && !line.ends_with('\n') {
+            return Err(Self::invalid("Chunk Line Too Long"));
+        }
+
+        Ok(line)
+    }
+
+    fn invalid(message: &str) -> io::Error {
+        io::Error::other(ReadError::Invalid(String::from(message)))
+    }
+}
+
+impl<R: BufRead> Read for BodyReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.is_done && !buf.is_empty() {
+            if self.remaining == 0 {
+                self.start_chunk()?;
+                continue;
+            }
+
+            let size = buf.len().min(self.remaining);
+            let read = self.source.read(&mut buf[..size]).map_err(|_| Self::invalid("Incomplete Request Body"))?;
+
+            if read == 0 {
+                return Err(Self::invalid("Incomplete Request Body"));
+            }
+
+            self.remaining -= read;
+
+            if self.remaining == 0 {
+                if self.is_chunked {
+                    self.end_chunk()?;
+                } else {
+                    self.is_done = true;
+                }
+            }
+
+            return Ok(read);
+        }
+
+        Ok(0)
+    }
+}
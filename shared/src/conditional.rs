@@ -0,0 +1,69 @@
+use crate::constants::{ETAG_HEADER, IF_MODIFIED_SINCE_HEADER, IF_NONE_MATCH_HEADER, LAST_MODIFIED_HEADER};
+use crate::http_date::HttpDate;
+use crate::request::{HttpMethod, Request};
+use crate::response::Response;
+use crate::wrust_traits::InjectStructTrait;
+
+/// The conditional headers of a GET or HEAD request, kept aside while the request is handled
+/// so the response can be turned into a `304 Not Modified` once its validators are known
+#[derive(Debug, Clone, Default)]
+pub struct Conditions {
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<String>
+}
+
+impl Conditions {
+    pub fn from_request<T: InjectStructTrait>(request: &Request<T>) -> Self {
+        // Other methods are never answered with a 304
+        if !matches!(request.method, HttpMethod::GET | HttpMethod::HEAD) {
+            return Self::default();
+        }
+
+        Conditions {
+            if_none_match: request.get_header(IF_NONE_MATCH_HEADER).cloned(),
+            if_modified_since: request.get_header(IF_MODIFIED_SINCE_HEADER).cloned()
+        }
+    }
+
+    /// Whether the client copy of a successful response is still valid
+    pub fn is_not_modified(&self, response: &Response) -> bool {
+        if response.get_status() != 200 {
+            return false;
+        }
+
+        // `If-Modified-Since` is ignored when `If-None-Match` is present
+        if let Some(if_none_match) = &self.if_none_match {
+            return match response.get_header(ETAG_HEADER) {
+                Some(etag) => Self::matches_etag(if_none_match, etag),
+                None => false
+            };
+        }
+
+        let since = self.if_modified_since.as_deref().and_then(HttpDate::parse);
+        let last_modified = response.get_header(LAST_MODIFIED_HEADER).and_then(|value| HttpDate::parse(value));
+
+        match (since, last_modified) {
+            (Some(since), Some(last_modified)) => last_modified <= since,
+            _ => false
+        }
+    }
+
+    /// Replace the response with a `304 Not Modified` when the client copy is still valid
+    pub fn apply(&self, response: &mut Response) -> bool {
+        if !self.is_not_modified(response) {
+            return false;
+        }
+
+        response.not_modified();
+        true
+    }
+
+    // `If-None-Match` uses the weak comparison, `W/"a"` matches `"a"`
+    fn matches_etag(if_none_match: &str, etag: &str) -> bool {
+        let etag = etag.trim().trim_start_matches("W/");
+
+        if_none_match.split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+    }
+}
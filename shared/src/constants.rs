@@ -1,11 +1,26 @@
 use std::collections::HashMap;
-use std::path::Path;
 use lazy_static::lazy_static;
 
 pub const CONTENT_TYPE_HEADER: &str = "Content-Type";
+pub const CONTENT_LENGTH_HEADER: &str = "Content-Length";
+pub const TRANSFER_ENCODING_HEADER: &str = "Transfer-Encoding";
+pub const ALLOW_HEADER: &str = "Allow";
+pub const CONNECTION_HEADER: &str = "Connection";
+pub const KEEP_ALIVE_HEADER: &str = "Keep-Alive";
+pub const SET_COOKIE_HEADER: &str = "Set-Cookie";
 pub const COOKIES_HEADER: &str = "Cookie";
 pub const USER_AGENT_HEADER: &str = "User-Agent";
+pub const ETAG_HEADER: &str = "ETag";
+pub const LAST_MODIFIED_HEADER: &str = "Last-Modified";
+pub const CACHE_CONTROL_HEADER: &str = "Cache-Control";
+pub const IF_NONE_MATCH_HEADER: &str = "If-None-Match";
+pub const IF_MODIFIED_SINCE_HEADER: &str = "If-Modified-Since";
+pub const RANGE_HEADER: &str = "Range";
+pub const IF_RANGE_HEADER: &str = "If-Range";
+pub const ACCEPT_RANGES_HEADER: &str = "Accept-Ranges";
+pub const CONTENT_RANGE_HEADER: &str = "Content-Range";
 pub const DEFAULT_CONTENT_TYPE: &str = "text/plain";
+pub const BINARY_CONTENT_TYPE: &str = "application/octet-stream";
 pub const DEFAULT_STATUS_CODE : &str = "OK";
 
 lazy_static! {
@@ -54,11 +69,16 @@ lazy_static! {
         result.insert(201, "Created");
         result.insert(202, "Accepted");
         result.insert(204, "No Content");
+        result.insert(206, "Partial Content");
+        result.insert(304, "Not Modified");
         result.insert(400, "Bad Request");
         result.insert(401, "Unauthorized");
         result.insert(403, "Forbidden");
         result.insert(404, "Not Found");
         result.insert(405, "Method Not Allowed");
+        result.insert(413, "Payload Too Large");
+        result.insert(416, "Range Not Satisfiable");
+        result.insert(422, "Unprocessable Content");
         result.insert(500, "Internal Server Error");
         result.insert(502, "Bad Gateway");
         result.insert(503, "Service Unavailable");
@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chacha20poly1305::{AeadCore, ChaCha20Poly1305, KeyInit, Nonce};
+use chacha20poly1305::aead::{Aead, OsRng, Payload};
+use chacha20poly1305::aead::rand_core::RngCore;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::http_date::HttpDate;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const NONCE_LENGTH: usize = 12;
+const KEY_SALT: &[u8] = b"wrust-cookie-key";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None
+}
+
+#[derive(Debug, Clone)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub expires: Option<SystemTime>,
+    pub max_age: Option<i64>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<SameSite>
+}
+
+/// Keys derived from the application secret, one to sign cookies and one to encrypt them
+pub struct CookieKey {
+    signing: [u8; 32],
+    encryption: [u8; 32]
+}
+
+/// The cookies of a request or a response.
+/// Signed and private cookies need the key configured on the application with `WRust::secret`.
+#[derive(Default, Clone)]
+pub struct CookieJar {
+    cookies: HashMap<String, Cookie>,
+    key: Option<Arc<CookieKey>>
+}
+
+impl SameSite {
+    pub fn as_str(&self) -> &str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None"
+        }
+    }
+}
+
+impl Cookie {
+    pub fn new(name: String, value: String) -> Self {
+        Self {
+            name,
+            value,
+            path: None,
+            domain: None,
+            expires: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+            same_site: None
+        }
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn max_age(mut self, seconds: i64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// The cookie as sent in a `Set-Cookie` header, attributes included
+    pub fn to_header_value(&self) -> String {
+        let mut result = format!("{}={}", self.name, self.value);
+
+        if let Some(path) = &self.path {
+            result.push_str(&format!("; Path={}", path));
+        }
+
+        if let Some(domain) = &self.domain {
+            result.push_str(&format!("; Domain={}", domain));
+        }
+
+        if let Some(expires) = self.expires {
+            result.push_str(&format!("; Expires={}", HttpDate::format(expires)));
+        }
+
+        if let Some(max_age) = self.max_age {
+            result.push_str(&format!("; Max-Age={}", max_age));
+        }
+
+        if self.secure {
+            result.push_str("; Secure");
+        }
+
+        if self.http_only {
+            result.push_str("; HttpOnly");
+        }
+
+        if let Some(same_site) = self.same_site {
+            result.push_str(&format!("; SameSite={}", same_site.as_str()));
+        }
+
+        result
+    }
+}
+
+impl CookieKey {
+    pub fn from_secret(secret: &str) -> Self {
+        Self {
+            signing: Self::derive(secret, "wrust-cookie-signing"),
+            encryption: Self::derive(secret, "wrust-cookie-encryption")
+        }
+    }
+
+    /// A random key, for when no secret is configured: cookies signed with it do not survive a restart
+    pub fn generate() -> Self {
+        let mut key = Self { signing: [0; 32], encryption: [0; 32] };
+        OsRng.fill_bytes(&mut key.signing);
+        OsRng.fill_bytes(&mut key.encryption);
+
+        key
+    }
+
+    // HKDF-SHA256 (RFC 5869), the info label gives each use its own independent key
+    fn derive(secret: &str, info: &str) -> [u8; 32] {
+        let mut extract = <HmacSha256 as Mac>::new_from_slice(KEY_SALT).expect("HMAC accepts keys of any size");
+        extract.update(secret.as_bytes());
+        let pseudo_random_key = extract.finalize().into_bytes();
+
+        // A single block of output is as long as a key
+        let mut expand = <HmacSha256 as Mac>::new_from_slice(&pseudo_random_key).expect("HMAC accepts keys of any size");
+        expand.update(info.as_bytes());
+        expand.update(&[1]);
+
+        expand.finalize().into_bytes().into()
+    }
+
+    // The name is part of the signature so a value can not be moved to another cookie
+    fn mac(&self, name: &str, value: &str) -> HmacSha256 {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.signing).expect("HMAC accepts keys of any size");
+        mac.update(name.as_bytes());
+        mac.update(b"=");
+        mac.update(value.as_bytes());
+
+        mac
+    }
+
+    pub fn sign(&self, name: &str, value: &str) -> String {
+        let signature = self.mac(name, value).finalize().into_bytes();
+        format!("{}.{}", value, URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    pub fn verify(&self, name: &str, signed_value: &str) -> Option<String> {
+        let (value, signature) = signed_value.rsplit_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+        self.mac(name, value).verify_slice(&signature).ok()?;
+        Some(value.to_string())
+    }
+
+    pub fn encrypt(&self, name: &str, value: &str) -> String {
+        let cipher = ChaCha20Poly1305::new((&self.encryption).into());
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload { msg: value.as_bytes(), aad: name.as_bytes() };
+
+        let mut result = nonce.to_vec();
+        result.extend(cipher.encrypt(&nonce, payload).expect("Encryption of an in-memory buffer can not fail"));
+
+        URL_SAFE_NO_PAD.encode(result)
+    }
+
+    pub fn decrypt(&self, name: &str, encrypted_value: &str) -> Option<String> {
+        let data = URL_SAFE_NO_PAD.decode(encrypted_value).ok()?;
+
+        if data.len() < NONCE_LENGTH {
+            return None;
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+        let cipher = ChaCha20Poly1305::new((&self.encryption).into());
+        let payload = Payload { msg: ciphertext, aad: name.as_bytes() };
+
+        let value = cipher.decrypt(Nonce::from_slice(nonce), payload).ok()?;
+        String::from_utf8(value).ok()
+    }
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_key(&mut self, key: Arc<CookieKey>) {
+        self.key = Some(key);
+    }
+
+    /// Add the cookies of a `Cookie` request header: `name=value; other=value`
+    pub fn add_from_header(&mut self, header_value: &str) {
+        for cookie in header_value.split(';') {
+            if let Some((cookie_name, cookie_value)) = cookie.trim().split_once('=') {
+                self.add(Cookie::new(String::from(cookie_name), String::from(cookie_value)));
+            }
+        }
+    }
+
+    /// The cookie with this name, matched case-insensitively like headers when no exact match exists
+    pub fn get(&self, name: &str) -> Option<&Cookie> {
+        self.cookies.get(name).or_else(|| self.cookies.values().find(|cookie| cookie.name.eq_ignore_ascii_case(name)))
+    }
+
+    pub fn get_value(&self, name: &str) -> Option<&String> {
+        self.get(name).map(|cookie| &cookie.value)
+    }
+
+    pub fn add(&mut self, cookie: Cookie) {
+        self.cookies.insert(cookie.name.clone(), cookie);
+    }
+
+    /// Ask the client to drop the cookie, by sending it back already expired
+    pub fn remove(&mut self, name: &str) {
+        let path = self.get(name).and_then(|cookie| cookie.path.clone()).unwrap_or(String::from("/"));
+        let cookie = Cookie::new(name.to_string(), String::new())
+            .path(&path)
+            .max_age(0)
+            .expires(UNIX_EPOCH);
+
+        self.add(cookie);
+    }
+
+    /// Add a cookie whose value is signed, so it can be read by the client but not tampered with
+    pub fn add_signed(&mut self, mut cookie: Cookie) -> Result<(), String> {
+        let key = self.get_key()?;
+
+        cookie.value = key.sign(&cookie.name, &cookie.value);
+        self.add(cookie);
+
+        Ok(())
+    }
+
+    /// The cookie with its verified value, `None` if it is missing or its signature does not match
+    pub fn get_signed(&self, name: &str) -> Option<Cookie> {
+        let key = self.key.as_ref()?;
+        let mut cookie = self.get(name)?.clone();
+
+        // The name it was signed under, whatever its case in the lookup
+        cookie.value = key.verify(&cookie.name, &cookie.value)?;
+        Some(cookie)
+    }
+
+    /// Add a cookie whose value is encrypted, so it can be neither read nor tampered with by the client
+    pub fn add_private(&mut self, mut cookie: Cookie) -> Result<(), String> {
+        let key = self.get_key()?;
+
+        cookie.value = key.encrypt(&cookie.name, &cookie.value);
+        self.add(cookie);
+
+        Ok(())
+    }
+
+    /// The cookie with its decrypted value, `None` if it is missing or can not be authenticated
+    pub fn get_private(&self, name: &str) -> Option<Cookie> {
+        let key = self.key.as_ref()?;
+        let mut cookie = self.get(name)?.clone();
+
+        cookie.value = key.decrypt(&cookie.name, &cookie.value)?;
+        Some(cookie)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.cookies.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    fn get_key(&self) -> Result<&Arc<CookieKey>, String> {
+        self.key.as_ref().ok_or(String::from("No secret configured to sign or encrypt cookies"))
+    }
+}
+
+impl std::fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Keep the key out of the logs
+        f.debug_struct("CookieJar").field("cookies", &self.cookies).finish()
+    }
+}
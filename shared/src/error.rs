@@ -1,22 +1,44 @@
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+use std::ops::Range;
 use serde::Serialize;
 
 pub type ErrorsHashMap = HashMap<String, String>;
 
-#[derive(Serialize)]
+// Invalid parts of a request, by field name, answered with `status`
+#[derive(Debug, Serialize)]
 pub struct RequestError {
     name: String,
-    errors: ErrorsHashMap
+    errors: ErrorsHashMap,
+    #[serde(skip)]
+    status: usize
 }
 
 impl RequestError {
     pub fn new(name: String) -> Self {
         Self {
             name,
-            errors: HashMap::new()
+            errors: HashMap::new(),
+            status: 400
         }
     }
 
+    /// Answer with another status than `400 Bad Request`, e.g. `422 Unprocessable Content`
+    pub fn status(mut self, status: usize) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn get_status(&self) -> usize {
+        self.status
+    }
+
+    pub fn get_errors(&self) -> &ErrorsHashMap {
+        &self.errors
+    }
+
     pub fn set_error(&mut self, name: String, value: String) -> &Self {
         self.errors.insert(name, value);
         self
@@ -25,4 +47,114 @@ impl RequestError {
     pub fn has_error(&self) -> bool {
         !self.errors.is_empty()
     }
-}
\ No newline at end of file
+}
+
+// Why a request could not be read from its connection, answered with a 400 or a 413
+#[derive(Debug, Clone)]
+pub enum ReadError {
+    Invalid(String),
+    TooLarge(usize)
+}
+
+impl ReadError {
+    pub fn get_status(&self) -> usize {
+        match self {
+            ReadError::Invalid(_) => 400,
+            ReadError::TooLarge(_) => 413
+        }
+    }
+}
+
+impl From<String> for ReadError {
+    fn from(message: String) -> Self {
+        ReadError::Invalid(message)
+    }
+}
+
+// Errors of the body reader carry the `ReadError` they stand for, other ones mean the body was cut short
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> Self {
+        match err.get_ref().and_then(|err| err.downcast_ref::<ReadError>()) {
+            Some(err) => err.clone(),
+            None => ReadError::Invalid(String::from("Incomplete Request Body"))
+        }
+    }
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadError::Invalid(message) => write!(f, "{}", message),
+            ReadError::TooLarge(max_body_size) => write!(f, "Request body larger than {} bytes", max_body_size)
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+// Why the server could not start listening or serving
+#[derive(Debug)]
+pub enum ServerError {
+    InvalidAddress(String),
+    AddressInUse(SocketAddr),
+    AddressNotAvailable(SocketAddr),
+    PermissionDenied(SocketAddr),
+    NoPortAvailable(Range<u16>),
+    Io(SocketAddr, io::Error),
+    // The listener is bound, but could not be set up to accept connections
+    Listen(SocketAddr, io::Error),
+    SignalHandler(String),
+    Template(TemplateError),
+    InvalidRoutes(Vec<String>)
+}
+
+impl ServerError {
+    pub fn from_bind_error(address: SocketAddr, err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::AddrInUse => ServerError::AddressInUse(address),
+            io::ErrorKind::AddrNotAvailable => ServerError::AddressNotAvailable(address),
+            io::ErrorKind::PermissionDenied => ServerError::PermissionDenied(address),
+            _ => ServerError::Io(address, err)
+        }
+    }
+}
+
+impl Display for ServerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerError::InvalidAddress(address) => write!(f, "Invalid address: {}", address),
+            ServerError::AddressInUse(address) => write!(f, "Address already in use: {}", address),
+            ServerError::AddressNotAvailable(address) => write!(f, "Address not available on this machine: {}", address),
+            ServerError::PermissionDenied(address) => write!(f, "Permission denied to bind {}", address),
+            ServerError::NoPortAvailable(range) => write!(f, "No port is available in this range [{}, {})", range.start, range.end),
+            ServerError::Io(address, err) => write!(f, "Failed to bind {}: {}", address, err),
+            ServerError::Listen(address, err) => write!(f, "Failed to accept connections on {}: {}", address, err),
+            ServerError::SignalHandler(err) => write!(f, "Failed to handle termination signals: {}", err),
+            ServerError::Template(err) => write!(f, "Failed to load the views: {}", err),
+            ServerError::InvalidRoutes(errors) => write!(f, "Invalid routes: {}", errors.join(", "))
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+// Why a view could not be rendered, with the name of the template involved
+#[derive(Debug)]
+pub enum TemplateError {
+    NotFound(String),
+    Io(String, io::Error),
+    Syntax(String, usize, String),
+    Render(String, String)
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateError::NotFound(name) => write!(f, "Template not found: {}", name),
+            TemplateError::Io(name, err) => write!(f, "Failed to read template {}: {}", name, err),
+            TemplateError::Syntax(name, line, message) => write!(f, "Syntax error in template {} at line {}: {}", name, line, message),
+            TemplateError::Render(name, message) => write!(f, "Failed to render template {}: {}", name, message)
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
@@ -1,19 +1,378 @@
-use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use memchr::memmem::Finder;
+use serde_json::{Map, Value};
+use crate::constants::{BINARY_CONTENT_TYPE, CONTENT_TYPE_HEADER};
+use crate::error::ReadError;
+use crate::url_encoding::UrlEncoding;
+
+// Default folder of uploaded files, inside the temporary directory of the system
+pub const DEFAULT_UPLOAD_DIR: &str = "wrust-uploads";
+
+const CONTENT_DISPOSITION_HEADER: &str = "Content-Disposition";
+const CONTENT_TRANSFER_ENCODING_HEADER: &str = "Content-Transfer-Encoding";
+const DEFAULT_ENCODING: &str = "7bit";
+
+// How much of a multipart body is read at once
+const READ_SIZE: usize = 8 * 1024;
+
+// Makes the names of files uploaded during the same nanosecond unique
+static UPLOADS_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Debug)]
 pub struct File {
-    name: String,
-    field_name: String,
-    original_name: String,
-    mime_type: String,
-    destination: String,
-    path: String,
-    encoding: String,
-    size: usize
+    pub name: String,
+    pub field_name: String,
+    pub original_name: String,
+    pub mime_type: String,
+    pub destination: String,
+    pub path: String,
+    pub encoding: String,
+    pub size: usize,
+    is_persisted: bool
 }
 
 #[derive(Debug)]
 pub struct  FormData {
-    data: Value,
-    files: Vec<File>
-}
\ No newline at end of file
+    pub data: Value,
+    pub files: Vec<File>
+}
+
+/// Where the files of `multipart/form-data` requests are written while they are received.
+/// Files are saved under generated names, their original names are kept in `File::original_name`.
+/// They are deleted when the request ends, unless the handler of the route keeps them with `File::persist`.
+#[derive(Debug, Clone)]
+pub struct UploadConfig {
+    pub destination: PathBuf
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self::new(env::temp_dir().join(DEFAULT_UPLOAD_DIR))
+    }
+}
+
+impl UploadConfig {
+    pub fn new<P: AsRef<Path>>(destination: P) -> Self {
+        UploadConfig {
+            destination: destination.as_ref().to_path_buf()
+        }
+    }
+}
+
+// The headers of a part of a multipart body, before its content becomes a field or a file
+struct Part {
+    headers: Vec<(String, String)>
+}
+
+// Reads a multipart body as it arrives, keeping in memory only what was not handed to a part yet
+struct MultipartReader<'a, R: Read> {
+    source: &'a mut R,
+    buffer: Vec<u8>
+}
+
+impl File {
+    /// Keep the file where it was uploaded once the request ends
+    pub fn persist(&mut self) {
+        self.is_persisted = true;
+    }
+
+    /// Move the file to `path` and keep it once the request ends
+    pub fn persist_to<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+
+        // Renaming fails across file systems, the file is copied there instead
+        fs::rename(&self.path, path)
+            .or_else(|_| fs::copy(&self.path, path).and_then(|_| fs::remove_file(&self.path)))
+            .map_err(|err| format!("Failed to move the uploaded file {:?}: {}", self.original_name, err))?;
+
+        self.name = path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string());
+        self.destination = path.parent().map_or(String::new(), |parent| parent.to_string_lossy().to_string());
+        self.path = path.to_string_lossy().to_string();
+        self.is_persisted = true;
+
+        Ok(())
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        if !self.is_persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl FormData {
+    pub fn new() -> Self {
+        FormData {
+            data: Value::Object(Map::new()),
+            files: Vec::new()
+        }
+    }
+
+    /// Add a field, repeated fields become arrays
+    pub fn add_field(&mut self, name: String, value: String) {
+        let Value::Object(data) = &mut self.data else {
+            return;
+        };
+
+        match data.get_mut(&name) {
+            Some(Value::Array(values)) => values.push(Value::String(value)),
+            Some(previous) => *previous = Value::Array(Vec::from([previous.take(), Value::String(value)])),
+            None => {
+                data.insert(name, Value::String(value));
+            }
+        }
+    }
+
+    pub fn get_field(&self, name: &str) -> Option<&Value> {
+        self.data.get(name)
+    }
+
+    pub fn get_file(&self, field_name: &str) -> Option<&File> {
+        self.files.iter().find(|file| file.field_name == field_name)
+    }
+
+    /// The file of a field, to persist it
+    pub fn get_file_mut(&mut self, field_name: &str) -> Option<&mut File> {
+        self.files.iter_mut().find(|file| file.field_name == field_name)
+    }
+
+    /// Parse a `multipart/form-data` body as it is read, writing its files to the upload destination.
+    /// Parts are separated by `--boundary` lines, and the last one is followed by `--boundary--`.
+    pub fn from_multipart<R: Read>(body: &mut R, content_type: &str, uploads: &UploadConfig) -> Result<Self, ReadError> {
+        let boundary = Self::get_boundary(content_type).ok_or(String::from("Missing Multipart Boundary"))?;
+        let delimiter = format!("--{}", boundary);
+        let separator = format!("\r\n--{}", boundary);
+        let (delimiter, separator, headers_end) = (Finder::new(&delimiter), Finder::new(&separator), Finder::new(b"\r\n\r\n"));
+
+        let mut reader = MultipartReader::new(body);
+        let mut form_data = FormData::new();
+
+        // The preamble before the first boundary is ignored
+        if reader.copy_until(&delimiter, &mut io::sink())?.is_none() {
+            return Err(ReadError::from(String::from("Missing Multipart Boundary")));
+        }
+
+        loop {
+            if reader.skip_prefix(b"--")? {
+                return Ok(form_data);
+            }
+
+            if !reader.skip_prefix(b"\r\n")? {
+                return Err(ReadError::from(String::from("Invalid Multipart Boundary")));
+            }
+
+            let mut headers = Vec::new();
+
+            if reader.copy_until(&headers_end, &mut headers)?.is_none() {
+                return Err(ReadError::from(String::from("Invalid Multipart Headers")));
+            }
+
+            let part = Part::new(&headers);
+            let disposition = part.get_header(CONTENT_DISPOSITION_HEADER).ok_or(String::from("Missing Content-Disposition"))?;
+            let params = Self::parse_params(disposition);
+
+            let Some(field_name) = params.get("name").cloned() else {
+                return Err(ReadError::from(String::from("Missing Field Name")));
+            };
+
+            match params.get("filename") {
+                Some(original_name) => {
+                    let mut file = Self::create_file(&part, field_name, original_name.clone(), uploads)?;
+                    let mut writer = fs::OpenOptions::new().write(true).open(&file.path)
+                        .map(BufWriter::new)
+                        .map_err(|err| Self::save_error(&file, err))?;
+
+                    file.size = reader.copy_until(&separator, &mut writer)?
+                        .ok_or(String::from("Unterminated Multipart Body"))?;
+                    writer.flush().map_err(|err| Self::save_error(&file, err))?;
+
+                    // Browsers send an empty file part when no file was chosen, dropping it removes it
+                    if !original_name.is_empty() || file.size > 0 {
+                        form_data.files.push(file);
+                    }
+                },
+                None => {
+                    let mut value = Vec::new();
+                    reader.copy_until(&separator, &mut value)?.ok_or(String::from("Unterminated Multipart Body"))?;
+
+                    form_data.add_field(field_name, String::from_utf8_lossy(&value).to_string());
+                }
+            }
+        }
+    }
+
+    /// Parse an `application/x-www-form-urlencoded` body, where `+` stands for a space
+    pub fn from_urlencoded(body: &[u8]) -> Result<Self, String> {
+        let body = String::from_utf8(body.to_vec()).map_err(|_| String::from("Invalid Form Encoding"))?;
+        let mut form_data = FormData::new();
+
+        for pair in body.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+
+            form_data.add_field(
+                UrlEncoding::url_decode_utf8(name.replace('+', " "))?,
+                UrlEncoding::url_decode_utf8(value.replace('+', " "))?
+            );
+        }
+
+        Ok(form_data)
+    }
+
+    // An empty file under a generated name, deleted when dropped unless it is persisted
+    fn create_file(part: &Part, field_name: String, original_name: String, uploads: &UploadConfig) -> Result<File, String> {
+        let mime_type = part.get_header(CONTENT_TYPE_HEADER).unwrap_or(BINARY_CONTENT_TYPE).to_string();
+        let encoding = part.get_header(CONTENT_TRANSFER_ENCODING_HEADER).unwrap_or(DEFAULT_ENCODING).to_string();
+
+        // The original name is never used on disk, it could contain a path
+        let extension = Path::new(&original_name).extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .filter(|extension| extension.len() <= 10 && extension.chars().all(|ch| ch.is_ascii_alphanumeric()));
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut name = format!("{:x}-{:x}-{:x}", since_epoch.as_nanos(), std::process::id(), UPLOADS_COUNTER.fetch_add(1, Ordering::Relaxed));
+
+        if let Some(extension) = extension {
+            name = format!("{}.{}", name, extension);
+        }
+
+        let path = uploads.destination.join(&name);
+
+        fs::create_dir_all(&uploads.destination)
+            .and_then(|_| fs::File::create_new(&path))
+            .map_err(|err| format!("Failed to save the uploaded file {:?}: {}", original_name, err))?;
+
+        Ok(File {
+            name,
+            field_name,
+            original_name,
+            mime_type,
+            destination: uploads.destination.to_string_lossy().to_string(),
+            path: path.to_string_lossy().to_string(),
+            encoding,
+            size: 0,
+            is_persisted: false
+        })
+    }
+
+    fn save_error(file: &File, err: io::Error) -> ReadError {
+        ReadError::from(format!("Failed to save the uploaded file {:?}: {}", file.original_name, err))
+    }
+
+    fn get_boundary(content_type: &str) -> Option<String> {
+        let (_, params) = content_type.split_once(';')?;
+        let boundary = Self::parse_params(params).remove("boundary")?;
+
+        if boundary.is_empty() {
+            return None;
+        }
+
+        Some(boundary)
+    }
+
+    // `form-data; name="field"; filename="a.png"` parameters, with the keys in lowercase
+    fn parse_params(value: &str) -> HashMap<String, String> {
+        value.split(';')
+            .filter_map(|param| param.split_once('='))
+            .map(|(key, value)| {
+                let value = value.trim();
+                let value = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value);
+
+                (key.trim().to_lowercase(), value.replace("\\\"", "\""))
+            })
+            .collect()
+    }
+}
+
+impl Default for FormData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Part {
+    fn new(headers: &[u8]) -> Self {
+        let headers = String::from_utf8_lossy(headers).lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+
+        Part { headers }
+    }
+
+    fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl<'a, R: Read> MultipartReader<'a, R> {
+    fn new(source: &'a mut R) -> Self {
+        MultipartReader {
+            source,
+            buffer: Vec::new()
+        }
+    }
+
+    // Read more of the body, false once it ended
+    fn fill(&mut self) -> Result<bool, ReadError> {
+        let start = self.buffer.len();
+        self.buffer.resize(start + READ_SIZE, 0);
+
+        let read = self.source.read(&mut self.buffer[start..]);
+        self.buffer.truncate(start + read.as_ref().map_or(0, |read| *read));
+
+        Ok(read? > 0)
+    }
+
+    // Hand what comes before the next `needle` to `sink` and skip the needle,
+    // returning the size of what was handed, or `None` when the body ends first
+    fn copy_until<W: Write>(&mut self, needle: &Finder, sink: &mut W) -> Result<Option<usize>, ReadError> {
+        let mut size = 0;
+
+        loop {
+            if let Some(index) = needle.find(&self.buffer) {
+                sink.write_all(&self.buffer[..index]).map_err(Self::write_error)?;
+                self.buffer.drain(..index + needle.needle().len());
+
+                return Ok(Some(size + index));
+            }
+
+            // The end of the buffer may be the start of the needle
+            let complete = self.buffer.len().saturating_sub(needle.needle().len() - 1);
+            sink.write_all(&self.buffer[..complete]).map_err(Self::write_error)?;
+            self.buffer.drain(..complete);
+            size += complete;
+
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn skip_prefix(&mut self, prefix: &[u8]) -> Result<bool, ReadError> {
+        while self.buffer.len() < prefix.len() {
+            if !self.fill()? {
+                return Ok(false);
+            }
+        }
+
+        if !self.buffer.starts_with(prefix) {
+            return Ok(false);
+        }
+
+        self.buffer.drain(..prefix.len());
+        Ok(true)
+    }
+
+    fn write_error(err: io::Error) -> ReadError {
+        ReadError::from(format!("Failed to save an uploaded file: {}", err))
+    }
+}
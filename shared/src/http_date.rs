@@ -0,0 +1,85 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+pub struct HttpDate;
+
+impl HttpDate {
+    /// Format a time as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+    pub fn format(time: SystemTime) -> String {
+        let seconds = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+        let days = seconds / 86400;
+        let seconds_of_day = seconds % 86400;
+
+        let (year, month, day) = Self::civil_from_days(days as i64);
+
+        format!(
+            "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+            DAYS[(days % 7) as usize],
+            day,
+            MONTHS[(month - 1) as usize],
+            year,
+            seconds_of_day / 3600,
+            (seconds_of_day % 3600) / 60,
+            seconds_of_day % 60
+        )
+    }
+
+    /// Parse an IMF-fixdate, the only format servers are required to send
+    pub fn parse(value: &str) -> Option<SystemTime> {
+        let parts = value.split_whitespace().collect::<Vec<&str>>();
+
+        let [day_name, day, month, year, time, "GMT"] = parts.as_slice() else {
+            return None;
+        };
+
+        if !day_name.ends_with(',') || !DAYS.contains(&day_name.trim_end_matches(',')) {
+            return None;
+        }
+
+        let day = day.parse::<u32>().ok().filter(|day| (1..=31).contains(day))?;
+        let month = MONTHS.iter().position(|name| name == month)? as u32 + 1;
+        let year = year.parse::<i64>().ok().filter(|year| *year >= 1970)?;
+
+        let time = time.split(':').map(|part| part.parse::<u64>().ok()).collect::<Option<Vec<u64>>>()?;
+        let [hours, minutes, seconds] = time.as_slice() else {
+            return None;
+        };
+
+        if *hours > 23 || *minutes > 59 || *seconds > 60 {
+            return None;
+        }
+
+        let days = Self::days_from_civil(year, month, day) as u64;
+
+        Some(UNIX_EPOCH + Duration::from_secs(days * 86400 + hours * 3600 + minutes * 60 + seconds))
+    }
+
+    // Howard Hinnant's civil-to-days algorithm, the inverse of `civil_from_days`
+    fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month_position = if month > 2 { month - 3 } else { month + 9 } as i64;
+        let day_of_year = (153 * month_position + 2) / 5 + day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+        era * 146097 + day_of_era - 719468
+    }
+
+    // Howard Hinnant's days-to-civil algorithm, days are counted from 1970-01-01
+    fn civil_from_days(days: i64) -> (i64, u32, u32) {
+        let days = days + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_position = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * month_position + 2) / 5 + 1) as u32;
+        let month = if month_position < 10 { month_position + 3 } else { month_position - 9 } as u32;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        (year, month, day)
+    }
+}
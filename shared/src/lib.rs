@@ -7,3 +7,11 @@ pub mod response;
 pub mod constants;
 pub mod wrust_traits;
 pub mod form_data;
+pub mod body;
+pub mod cookie;
+pub mod http_date;
+pub mod conditional;
+pub mod range;
+pub mod template;
+pub mod middleware;
+pub mod validation;
\ No newline at end of file
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use crate::request::Request;
+use crate::response::Response;
+
+/// Code running around route handlers, e.g. logging, authentication or CORS.
+/// A middleware either calls `next.run(request, response)` to continue with the rest of the chain,
+/// or answers by itself to short-circuit it.
+pub trait Middleware: Send + Sync {
+    fn handle<'a>(&self, request: Request, response: &'a mut Response, next: Next<'_>) -> &'a Response;
+}
+
+/// What the chain of middleware ends with, usually a route handler
+pub type NextHandler<'a> = dyn Fn(Request, &mut Response) -> &Response + Sync + Send + 'a;
+
+/// The middleware left to run in the chain, followed by the route handler
+pub struct Next<'a> {
+    middlewares: &'a [Arc<dyn Middleware>],
+    handler: &'a NextHandler<'a>
+}
+
+impl<'a> Next<'a> {
+    pub fn new(middlewares: &'a [Arc<dyn Middleware>], handler: &'a NextHandler<'a>) -> Self {
+        Next {
+            middlewares,
+            handler
+        }
+    }
+
+    pub fn run(self, request: Request, response: &mut Response) -> &Response {
+        match self.middlewares.split_first() {
+            Some((middleware, middlewares)) => {
+                middleware.handle(request, response, Next::new(middlewares, self.handler))
+            },
+            None => (self.handler)(request, response)
+        }
+    }
+}
+
+/// A middleware written as a closure, see `from_fn`
+pub struct FnMiddleware<F> {
+    handler: F
+}
+
+/// Turn a closure into a middleware:
+/// `from_fn(|request, response, next| next.run(request, response))`
+pub fn from_fn<F>(handler: F) -> Arc<dyn Middleware>
+where F: for<'a, 'b> Fn(Request, &'a mut Response, Next<'b>) -> &'a Response + Send + Sync + 'static {
+    Arc::new(FnMiddleware { handler })
+}
+
+impl<F> Middleware for FnMiddleware<F>
+where F: for<'a, 'b> Fn(Request, &'a mut Response, Next<'b>) -> &'a Response + Send + Sync {
+    fn handle<'a>(&self, request: Request, response: &'a mut Response, next: Next<'_>) -> &'a Response {
+        (self.handler)(request, response, next)
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use crate::query::QueryParamValue::{Multiple, Single};
 use crate::query::QueryParamValueType::{Str, Int, Float, Boolean, UInt};
+use crate::validation::Rule;
 
 pub type QueriesHashMap = HashMap<String, QueryParamType>;
 
@@ -19,17 +20,18 @@ pub enum QueryParamValue {
     Multiple(Vec<QueryParamValueType>)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Flags {
     pub is_optional: bool,
     pub is_array: bool,
     pub allow_empty: bool
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct QueryParamType {
     pub _type: QueryParamValueType,
-    pub flags: Flags
+    pub flags: Flags,
+    pub rules: Vec<Rule>
 }
 
 #[derive(Debug, Clone)]
@@ -83,12 +85,12 @@ impl QueryParam {
         None
     }
 
-    fn generate_data(string_value: &String, _type: QueryParamValueType) -> Option<QueryParamValueType> {
+    fn generate_data(string_value: &str, _type: QueryParamValueType) -> Option<QueryParamValueType> {
         let mut data = None;
 
         match _type {
             Str(_) => {
-                data = Some(Str(string_value.clone()));
+                data = Some(Str(string_value.to_string()));
             },
             Int(_) => {
                 if let Ok(value) = string_value.parse::<isize>() {
@@ -106,7 +108,7 @@ impl QueryParam {
                 }
             },
             Boolean(_) => {
-                let value = matches!(string_value.as_str(), "true" | "t" | "1");
+                let value = matches!(string_value, "true" | "t" | "1");
                 data = Some(Boolean(value));
             }
         };
@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use crate::constants::{ETAG_HEADER, IF_RANGE_HEADER, LAST_MODIFIED_HEADER, RANGE_HEADER};
+use crate::http_date::HttpDate;
+use crate::request::{HttpMethod, Request};
+use crate::response::Response;
+use crate::wrust_traits::InjectStructTrait;
+
+// More ranges than this are answered with the whole body, many tiny ranges cost more than they save
+const MAX_RANGES: usize = 16;
+
+/// An inclusive range of bytes, already resolved against the length of the body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64
+}
+
+impl ByteRange {
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// Parse a `Range` header value like `bytes=0-99,200-,-500` against a body of `length` bytes.
+    /// `None` when the header is invalid and must be ignored, an empty list when no range can be satisfied.
+    pub fn parse(value: &str, length: u64) -> Option<Vec<ByteRange>> {
+        let (unit, specs) = value.trim().split_once('=')?;
+
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+
+        let mut ranges = Vec::new();
+
+        for spec in specs.split(',').map(|spec| spec.trim()).filter(|spec| !spec.is_empty()) {
+            let (start, end) = spec.split_once('-')?;
+
+            let range = if start.is_empty() {
+                // Suffix range, the last `end` bytes
+                let suffix = end.parse::<u64>().ok()?;
+
+                if suffix == 0 || length == 0 {
+                    continue;
+                }
+
+                ByteRange { start: length.saturating_sub(suffix), end: length - 1 }
+            } else {
+                let start = start.parse::<u64>().ok()?;
+                let end = if end.is_empty() { None } else { Some(end.parse::<u64>().ok()?) };
+
+                if end.is_some_and(|end| end < start) {
+                    return None;
+                }
+
+                if start >= length {
+                    continue;
+                }
+
+                ByteRange { start, end: end.unwrap_or(u64::MAX).min(length - 1) }
+            };
+
+            ranges.push(range);
+        }
+
+        if ranges.len() > MAX_RANGES {
+            return None;
+        }
+
+        Some(Self::coalesce(ranges))
+    }
+
+    // Overlapping and adjacent ranges are merged so no byte is sent twice
+    fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+        ranges.sort_by_key(|range| range.start);
+
+        let mut result: Vec<ByteRange> = Vec::new();
+
+        for range in ranges {
+            match result.last_mut() {
+                Some(last) if range.start <= last.end.saturating_add(1) => last.end = last.end.max(range.end),
+                _ => result.push(range)
+            }
+        }
+
+        result
+    }
+}
+
+/// The `Range` and `If-Range` headers of a GET request, kept aside while the request is handled
+/// so a successful response can be narrowed down to the requested ranges
+#[derive(Debug, Clone, Default)]
+pub struct RangeRequest {
+    pub range: Option<String>,
+    pub if_range: Option<String>
+}
+
+impl RangeRequest {
+    pub fn from_request<T: InjectStructTrait>(request: &Request<T>) -> Self {
+        // Ranges only apply to GET, HEAD answers with the headers of the whole body
+        if request.method != HttpMethod::GET {
+            return Self::default();
+        }
+
+        RangeRequest {
+            range: request.get_header(RANGE_HEADER).cloned(),
+            if_range: request.get_header(IF_RANGE_HEADER).cloned()
+        }
+    }
+
+    /// Answer with `206 Partial Content` or `416 Range Not Satisfiable` when ranges were asked for
+    pub fn apply(&self, response: &mut Response) -> bool {
+        let Some(range) = &self.range else {
+            return false;
+        };
+
+        if response.get_status() != 200 || !self.is_current(response) {
+            return false;
+        }
+
+        let Some(length) = response.get_range_length() else {
+            return false;
+        };
+
+        match ByteRange::parse(range, length) {
+            Some(ranges) if ranges.is_empty() => {
+                response.range_not_satisfiable(length);
+                true
+            },
+            Some(ranges) => {
+                response.partial_content(&ranges);
+                true
+            },
+            None => false
+        }
+    }
+
+    // `If-Range` asks for the ranges only if the representation did not change, using the strong comparison
+    fn is_current(&self, response: &Response) -> bool {
+        let Some(if_range) = self.if_range.as_deref().map(|value| value.trim()) else {
+            return true;
+        };
+
+        if if_range.starts_with('"') {
+            return response.get_header(ETAG_HEADER).is_some_and(|etag| etag.trim() == if_range);
+        }
+
+        if if_range.starts_with("W/") {
+            return false;
+        }
+
+        match (HttpDate::parse(if_range), response.get_header(LAST_MODIFIED_HEADER).and_then(|value| HttpDate::parse(value))) {
+            (Some(if_range), Some(last_modified)) => if_range == last_modified,
+            _ => false
+        }
+    }
+}
+
+/// Reads the parts of a `multipart/byteranges` body one after the other,
+/// seeking the source to each range when its part starts
+pub struct RangesReader<R: Read + Seek> {
+    source: R,
+    parts: VecDeque<(Vec<u8>, ByteRange)>,
+    closing: Vec<u8>,
+    header: Vec<u8>,
+    position: usize,
+    remaining: u64
+}
+
+impl<R: Read + Seek> RangesReader<R> {
+    /// Every part is preceded by its header, the closing delimiter is sent last
+    pub fn new(source: R, parts: Vec<(Vec<u8>, ByteRange)>, closing: Vec<u8>) -> Self {
+        RangesReader {
+            source,
+            parts: VecDeque::from(parts),
+            closing,
+            header: Vec::new(),
+            position: 0,
+            remaining: 0
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for RangesReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.position < self.header.len() {
+                let size = buf.len().min(self.header.len() - self.position);
+                buf[..size].copy_from_slice(&self.header[self.position..self.position + size]);
+                self.position += size;
+
+                return Ok(size);
+            }
+
+            if self.remaining > 0 {
+                let size = buf.len().min(self.remaining.min(usize::MAX as u64) as usize);
+                let read = self.source.read(&mut buf[..size])?;
+
+                if read == 0 {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Range shorter than announced"));
+                }
+
+                self.remaining -= read as u64;
+                return Ok(read);
+            }
+
+            match self.parts.pop_front() {
+                Some((header, range)) => {
+                    self.source.seek(SeekFrom::Start(range.start))?;
+                    self.header = header;
+                    self.remaining = range.length();
+                },
+                None if !self.closing.is_empty() => self.header = std::mem::take(&mut self.closing),
+                None => return Ok(0)
+            }
+
+            self.position = 0;
+        }
+    }
+}
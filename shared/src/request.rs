@@ -1,26 +1,36 @@
 use std::string::String;
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{self, BufRead, BufReader, Read};
 use std::net::TcpStream;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
-use crate::constants::{CONTENT_TYPE_HEADER, COOKIES_HEADER, DEFAULT_CONTENT_TYPE, USER_AGENT_HEADER};
-use crate::error::RequestError;
-use crate::form_data::FormData;
+use crate::body::BodyReader;
+use crate::constants::{CONNECTION_HEADER, CONTENT_TYPE_HEADER, COOKIES_HEADER, DEFAULT_CONTENT_TYPE, USER_AGENT_HEADER};
+use crate::cookie::CookieJar;
+use crate::error::{ReadError, RequestError};
+use crate::form_data::{FormData, UploadConfig};
 use crate::wrust_traits::InjectStructTrait;
 use crate::query::{QueriesHashMap, QueryParam, QueryParamValueType::{Str}};
 use crate::query::QueryParamValue::Multiple;
-use crate::request::RequestData::{Json, Text};
-use crate::route::{RouteMethod::{self, RouteGet, RoutePost}};
+use crate::request::RequestData::{Form, Json, Text};
+use crate::route::RouteMethod::{self, RouteCustom, RouteDelete, RouteGet, RouteHead, RouteOptions, RoutePatch, RoutePost, RoutePut};
 use crate::url_encoding::UrlEncoding;
+use crate::validation::Rule;
 
 pub type RequestQueriesHashMap = HashMap<String, QueryParam>;
 pub type RequestHeadersHashMap = HashMap<String, String>;
-pub type RequestCookiesHashMap = HashMap<String, String>;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HttpMethod {
     GET,
-    POST
+    POST,
+    PUT,
+    PATCH,
+    DELETE,
+    HEAD,
+    OPTIONS,
+    // Extension methods like PROPFIND or PURGE
+    Custom(String)
 }
 
 #[derive(Debug)]
@@ -41,8 +51,8 @@ pub struct HttpRequestFirstLine {
 
 #[derive(Debug)]
 pub struct IpAddress {
-    value: String,
-    is_ipv6: bool
+    pub value: String,
+    pub is_ipv6: bool
 }
 
 #[derive(Debug)]
@@ -51,14 +61,17 @@ pub struct Request<T: InjectStructTrait = RequestQueriesHashMap>
     pub path: String,
     pub method: HttpMethod,
     pub headers: RequestHeadersHashMap,
-    pub cookies: RequestCookiesHashMap,
+    pub cookies: CookieJar,
     pub queries_map: RequestQueriesHashMap,
+    pub form_map: RequestQueriesHashMap,
+    pub params: RequestQueriesHashMap,
     pub user_agent: String,
     pub ip: IpAddress,
     pub data: RequestData,
     pub http_version: String,
     pub query_string: String,
-    pub queries: T
+    pub queries: T,
+    body: Vec<u8>
 }
 
 impl InjectStructTrait for RequestQueriesHashMap {
@@ -69,7 +82,7 @@ impl InjectStructTrait for RequestQueriesHashMap {
     fn from_hashmap(hashmap: &RequestQueriesHashMap) -> Self where Self: Sized {
         let mut result = RequestQueriesHashMap::new();
 
-        for (key, &ref value) in hashmap {
+        for (key, value) in hashmap {
             result.insert(key.clone(), value.clone());
         }
 
@@ -78,10 +91,55 @@ impl InjectStructTrait for RequestQueriesHashMap {
 }
 
 impl HttpMethod {
+    pub fn from(method: &str) -> Option<Self> {
+        let method = match method {
+            "GET" => HttpMethod::GET,
+            "POST" => HttpMethod::POST,
+            "PUT" => HttpMethod::PUT,
+            "PATCH" => HttpMethod::PATCH,
+            "DELETE" => HttpMethod::DELETE,
+            "HEAD" => HttpMethod::HEAD,
+            "OPTIONS" => HttpMethod::OPTIONS,
+            _ => {
+                // A method is a token: visible ASCII chars without separators
+                let is_token = !method.is_empty() && method.chars().all(|ch| {
+                    ch.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(ch)
+                });
+
+                if !is_token {
+                    return None;
+                }
+
+                HttpMethod::Custom(method.to_string())
+            }
+        };
+
+        Some(method)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            HttpMethod::GET => "GET",
+            HttpMethod::POST => "POST",
+            HttpMethod::PUT => "PUT",
+            HttpMethod::PATCH => "PATCH",
+            HttpMethod::DELETE => "DELETE",
+            HttpMethod::HEAD => "HEAD",
+            HttpMethod::OPTIONS => "OPTIONS",
+            HttpMethod::Custom(method) => method.as_str()
+        }
+    }
+
     pub fn get_route_method(&self) -> RouteMethod {
         match self {
             HttpMethod::GET => RouteGet,
-            HttpMethod::POST => RoutePost
+            HttpMethod::POST => RoutePost,
+            HttpMethod::PUT => RoutePut,
+            HttpMethod::PATCH => RoutePatch,
+            HttpMethod::DELETE => RouteDelete,
+            HttpMethod::HEAD => RouteHead,
+            HttpMethod::OPTIONS => RouteOptions,
+            HttpMethod::Custom(method) => RouteCustom(method.clone())
         }
     }
 }
@@ -96,7 +154,7 @@ impl IpAddress {
 }
 
 impl<T: InjectStructTrait> Request<T> {
-    fn from(request_line: HttpRequestFirstLine, headers: RequestHeadersHashMap, cookies: RequestCookiesHashMap, ip: IpAddress, data: RequestData) -> Self {
+    fn from(request_line: HttpRequestFirstLine, headers: RequestHeadersHashMap, cookies: CookieJar, ip: IpAddress, data: RequestData) -> Self {
         Self {
             path: request_line.path,
             method: request_line.method,
@@ -104,7 +162,10 @@ impl<T: InjectStructTrait> Request<T> {
             http_version: request_line.http_version,
             query_string: request_line.query_string,
             queries_map: RequestQueriesHashMap::new(),
+            form_map: RequestQueriesHashMap::new(),
+            params: RequestQueriesHashMap::new(),
             queries: T::init(),
+            body: Vec::new(),
             data,
             headers,
             cookies,
@@ -114,26 +175,101 @@ impl<T: InjectStructTrait> Request<T> {
 
     pub fn map_queries(&mut self, queries_hash_map: &QueriesHashMap) -> Result<(), RequestError> {
         let query_string = self.query_string.clone();
-
-        for param in query_string.split("&") {
+        let pairs = query_string.split("&").map(|param| {
             let param_split = param.trim().split("=").collect::<Vec<&str>>();
+            let param_value = if param_split.len() > 1 { param_split[1] } else { "" };
 
-            if param_split.is_empty() {
-                continue;
-            }
+            (String::from(param_split[0]), String::from(param_value))
+        });
+
+        Self::map_params(pairs, queries_hash_map, &mut self.queries_map);
+        Self::validate_params(queries_hash_map, &self.queries_map, "query string", T::rules())?;
 
-            let param_name = String::from(param_split[0]);
-            let param_value = String::from(
-                if param_split.len() > 1  {
-                    param_split[1]
-                } else {
-                    ""
+        self.queries = T::from_hashmap(&self.queries_map);
+
+        Ok(())
+    }
+
+    /// Map the fields of a form body to `form_map`, typed and validated like the queries.
+    /// Other bodies have no fields, so only a schema without required fields accepts them.
+    pub fn map_form(&mut self, form_hash_map: &QueriesHashMap) -> Result<(), RequestError> {
+        let mut pairs = Vec::new();
+
+        if let Form(form_data) = &self.data {
+            if let Value::Object(fields) = &form_data.data {
+                for (name, value) in fields {
+                    match value {
+                        Value::Array(values) => pairs.extend(values.iter().map(|value| (name.clone(), value.as_str().unwrap_or_default().to_string()))),
+                        value => pairs.push((name.clone(), value.as_str().unwrap_or_default().to_string()))
+                    }
                 }
-            );
+            }
+        }
+
+        Self::map_params(pairs.into_iter(), form_hash_map, &mut self.form_map);
+        Self::validate_params(form_hash_map, &self.form_map, "body", &[])
+    }
+
+    /// Check the path params against the rules of their segments, e.g. `/people/:id:uint|min=1`
+    pub fn validate_path(&self, params_hash_map: &QueriesHashMap) -> Result<(), RequestError> {
+        Self::validate_params(params_hash_map, &self.params, "path", &[])
+    }
 
-            let value = match self.queries_map.get_mut(param) {
+    /// Deserialize the JSON body, e.g. `request.json::<CreatePerson>()`.
+    /// A body that is not JSON is a `400` error, and one of the wrong shape a `422` error on the field serde rejected,
+    /// named like serde paths, e.g. `address.city` or `tags[1]`.
+    pub fn json<B: DeserializeOwned>(&self) -> Result<B, RequestError> {
+        let parsed;
+        let value = match &self.data {
+            Json(value) => value,
+            _ => {
+                parsed = serde_json::from_slice::<Value>(&self.body).map_err(|err| {
+                    let mut request_error = RequestError::new(String::from("body"));
+                    request_error.set_error(String::from("body"), format!("Invalid Json: {}", err));
+                    request_error
+                })?;
+
+                &parsed
+            }
+        };
+
+        serde_path_to_error::deserialize::<_, B>(value).map_err(|err| {
+            // Errors of the top level value, like one of its fields missing, have an empty path
+            let name = match err.path().iter().next() {
+                Some(_) => err.path().to_string(),
+                None => String::from("body")
+            };
+
+            let mut request_error = RequestError::new(String::from("body")).status(422);
+            request_error.set_error(name, err.into_inner().to_string());
+            request_error
+        })
+    }
+
+    /// Deserialize the JSON body like `json`, then check the `#[validate]` rules of its `InjectStruct` fields
+    pub fn validated_json<B: DeserializeOwned + InjectStructTrait>(&self) -> Result<B, RequestError> {
+        let body = self.json::<B>()?;
+        let errors = body.check_rules();
+
+        if !errors.is_empty() {
+            let mut request_error = RequestError::new(String::from("body")).status(422);
+
+            for (name, error) in errors {
+                request_error.set_error(name, error);
+            }
+
+            return Err(request_error);
+        }
+
+        Ok(body)
+    }
+
+    // Parse `name=value` pairs to the types of their specs, undeclared params are string arrays
+    fn map_params<I: Iterator<Item = (String, String)>>(pairs: I, specs: &QueriesHashMap, params: &mut RequestQueriesHashMap) {
+        for (param_name, param_value) in pairs {
+            let value = match params.get_mut(&param_name) {
                 Some(query_param) => {
-                    match queries_hash_map.get(&param_name) {
+                    match specs.get(&param_name) {
                         Some(query_param_type) => {
                             query_param.add_value(param_value, query_param_type._type.clone())
                         },
@@ -143,7 +279,7 @@ impl<T: InjectStructTrait> Request<T> {
                     }
                 },
                 None => {
-                    match queries_hash_map.get(&param_name) {
+                    match specs.get(&param_name) {
                         Some(query_param_type) => {
                             QueryParam::from(param_value, query_param_type._type.clone(), query_param_type.flags.is_array)
                         },
@@ -155,14 +291,17 @@ impl<T: InjectStructTrait> Request<T> {
             };
 
             if let Some(query_param) = value {
-                self.queries_map.insert(param_name, query_param);
+                params.insert(param_name, query_param);
             }
         }
+    }
 
-        let mut request_error = RequestError::new(String::from("query string"));
+    // Presence and rules of the declared params, plus the rules of the struct they are injected into
+    fn validate_params(specs: &QueriesHashMap, params: &RequestQueriesHashMap, source: &str, struct_rules: &[(&str, Vec<Rule>)]) -> Result<(), RequestError> {
+        let mut request_error = RequestError::new(String::from(source));
 
-        for (name, param_type) in queries_hash_map {
-            if !param_type.flags.is_optional && !self.queries_map.contains_key(name) {
+        for (name, param_type) in specs {
+            if !param_type.flags.is_optional && !params.contains_key(name) {
                 if param_type.flags.is_array && !param_type.flags.allow_empty {
                     request_error.set_error(name.clone(), format!("{} can not be empty", name.clone()));
                     continue;
@@ -171,7 +310,7 @@ impl<T: InjectStructTrait> Request<T> {
                 continue;
             }
 
-            if let Some(query) = self.queries_map.get(name) {
+            if let Some(query) = params.get(name) {
                 if let Multiple(value) = &query.value {
                     if value.is_empty() && !param_type.flags.allow_empty {
                         request_error.set_error(name.clone(), format!("{} can not be empty", name.clone()));
@@ -184,27 +323,38 @@ impl<T: InjectStructTrait> Request<T> {
             }
         }
 
+        let rules = specs.iter()
+            .map(|(name, param_type)| (name.as_str(), &param_type.rules))
+            .chain(struct_rules.iter().map(|(name, rules)| (*name, rules)));
+
+        for (name, rules) in rules {
+            if let (Some(param), false) = (params.get(name), request_error.get_errors().contains_key(name)) {
+                if let Err(err) = Rule::check_param(rules, name, param) {
+                    request_error.set_error(name.to_string(), err);
+                }
+            }
+        }
+
         if request_error.has_error() {
             return Err(request_error);
         }
 
-        self.queries = T::from_hashmap(&self.queries_map);
-
         Ok(())
     }
 
-    pub fn read_request_data(stream: &TcpStream) -> Result<Request<T>, String> {
-        // Read request headers from input stream, to provide efficient reading of chars, arrays, and lines
-        let mut buf_reader = BufReader::new(stream);
-
+    /// Read the next request of the connection.
+    /// The reader is kept by the caller between requests, so the bytes of pipelined requests are not lost.
+    /// Bodies larger than `max_body_size` bytes are refused without being read.
+    pub fn read_request_data(buf_reader: &mut BufReader<TcpStream>, uploads: &UploadConfig, max_body_size: usize) -> Result<Request<T>, ReadError> {
         // Store Headers Here
         let mut http_request_header = Vec::new();
 
-        // Content Length used to extract the body
+        // Content Length or chunked Transfer Encoding used to extract the body
         let mut content_length = 0usize;
+        let mut is_chunked = false;
 
         // Ip Address
-        let ip = if let Ok(socket_addr) = stream.peer_addr() {
+        let ip = if let Ok(socket_addr) = buf_reader.get_ref().peer_addr() {
             let value = socket_addr.ip().to_string();
 
             IpAddress {
@@ -212,56 +362,75 @@ impl<T: InjectStructTrait> Request<T> {
                 is_ipv6: socket_addr.is_ipv6()
             }
         } else {
-            return Err(String::from("No Ip Address Specified"));
+            return Err(ReadError::Invalid(String::from("No Ip Address Specified")));
         };
 
         // Iterate over lines till finding an empty line (NO CRLF \r\n)
         for line in buf_reader.by_ref().lines() {
             if let Ok(line) = line {
                 if line.is_empty() {
+                    // Clients may send stray line breaks between requests on a kept alive connection
+                    if http_request_header.is_empty() {
+                        continue;
+                    }
+
                     break;
                 }
 
                 if line.to_lowercase().starts_with("content-length:") {
-                    if let Ok(value) = line["content-length:".len()..].trim().parse::<usize>() {
-                        content_length = value;
-                    }
+                    content_length = match line["content-length:".len()..].trim().parse::<usize>() {
+                        Ok(value) => value,
+                        Err(_) => return Err(ReadError::Invalid(String::from("Invalid Content-Length")))
+                    };
+                }
+
+                if line.to_lowercase().starts_with("transfer-encoding:") {
+                    is_chunked = line["transfer-encoding:".len()..].to_lowercase().contains("chunked");
                 }
 
                 http_request_header.push(line);
                 continue;
             }
 
-            return Err(String::from("Invalid Http Request"));
+            return Err(ReadError::Invalid(String::from("Invalid Http Request")));
         }
 
         if let Some(request_first_line) = http_request_header.first() {
-            println!("processing {:?}", request_first_line);
-
             let request_line = Self::extract_request_line(request_first_line)?;
             let (headers, cookies) = Self::extract_headers_and_cookies(&http_request_header);
 
-            let content_type = headers.get(CONTENT_TYPE_HEADER).unwrap_or(&String::from(DEFAULT_CONTENT_TYPE)).clone();
-            let data = Self::extract_request_data(buf_reader.by_ref(), request_line.method, content_length, content_type);
+            let content_type = headers.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(CONTENT_TYPE_HEADER))
+                .map_or(String::from(DEFAULT_CONTENT_TYPE), |(_, value)| value.clone());
+            let mut body_reader = if is_chunked {
+                BodyReader::chunked(buf_reader, max_body_size)
+            } else {
+                BodyReader::sized(buf_reader, content_length, max_body_size)?
+            };
+            let (data, body) = Self::extract_request_data(&mut body_reader, &content_type, uploads)?;
+
+            // The body is always consumed, otherwise it would be read as the next request of the connection
+            io::copy(&mut body_reader, &mut io::sink())?;
+
+            let mut request = Self::from(request_line, headers, cookies, ip, data);
+            request.body = body;
 
-            let request = Self::from(request_line, headers, cookies, ip, data);
             return Ok(request);
         }
 
-        Err(String::from("Invalid Http Request"))
+        Err(ReadError::Invalid(String::from("Invalid Http Request")))
     }
 
-    fn extract_request_line(request: &String) -> Result<HttpRequestFirstLine, String> {
+    fn extract_request_line(request: &str) -> Result<HttpRequestFirstLine, String> {
         let request_split = request.split(' ').collect::<Vec<&str>>();
 
         if request_split.len() != 3 {
             return Err(String::from("Invalid Http Request"));
         }
 
-        let method = match request_split[0] {
-            "GET" => HttpMethod::GET,
-            "POST" => HttpMethod::POST,
-            _ => return Err(String::from("Unknown Method"))
+        let method = match HttpMethod::from(request_split[0]) {
+            Some(method) => method,
+            None => return Err(String::from("Unknown Method"))
         };
 
         let mut path = UrlEncoding::url_decode(String::from(request_split[1]))?;
@@ -274,39 +443,31 @@ impl<T: InjectStructTrait> Request<T> {
 
         let http_version = request_split[2];
 
-        if !["HTTP/1.1", "HTTP/2"].contains(&http_version) {
+        if !["HTTP/1.0", "HTTP/1.1", "HTTP/2"].contains(&http_version) {
             return Err(String::from("Invalid Http Version"))
         }
 
         let http_version = String::from(http_version);
 
-        return Ok(HttpRequestFirstLine {
+        Ok(HttpRequestFirstLine {
             method,
             path,
             http_version,
             query_string
-        });
+        })
     }
 
-    fn extract_headers_and_cookies(request: &Vec<String>) -> (RequestHeadersHashMap, RequestCookiesHashMap) {
+    fn extract_headers_and_cookies(request: &[String]) -> (RequestHeadersHashMap, CookieJar) {
         let mut headers = RequestHeadersHashMap::new();
-        let mut cookies = RequestCookiesHashMap::new();
+        let mut cookies = CookieJar::new();
 
-        for i in 1..request.len() {
-            if let Some((header_name, header_value)) = request[i].split_once(": ") {
+        for line in request.iter().skip(1) {
+            if let Some((header_name, header_value)) = line.split_once(": ") {
                 let header_value = String::from(header_value);
 
                 match header_name {
                     COOKIES_HEADER => {
-                        let cookies_array = header_value.split("; ").collect::<Vec<&str>>();
-                        for cookie in cookies_array {
-                            if let Some((cookie_name, cookie_value)) = cookie.split_once('=') {
-                                let cookie_name = String::from(cookie_name);
-                                let cookie_value = String::from(cookie_value);
-
-                                cookies.insert(cookie_name, cookie_value);
-                            }
-                        }
+                        cookies.add_from_header(&header_value);
                     },
                     _ => {
                         headers.insert(String::from(header_name), header_value);
@@ -319,30 +480,60 @@ impl<T: InjectStructTrait> Request<T> {
         (headers, cookies)
     }
 
-    fn extract_request_data(buf_reader: &mut BufReader<&TcpStream>, method: HttpMethod, content_length: usize, content_type: String) -> RequestData {
-        match method {
-            HttpMethod::POST => {
-                match content_type.to_lowercase().as_str() {
-                    "application/json" => {
-                        let mut body = vec![0; content_length];
-                        buf_reader.read_exact(&mut body).unwrap();
-
-                        match String::from_utf8(body) {
-                            Ok(body_string) => {
-                                match serde_json::from_str(body_string.as_str()) {
-                                    Ok(value) => return Json(value),
-                                    _ => ()
-                                }
-                            },
-                            _ => ()
-                        }
-                    },
-                    _ => ()
-                }
-            },
-            _ => ()
-        };
+    /// The raw bytes of the body, empty for `multipart/form-data` bodies which are streamed to their fields and files
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Whether the client wants the connection to stay open after this request
+    pub fn is_keep_alive(&self) -> bool {
+        let connection = self.get_header(CONNECTION_HEADER).map(|value| value.to_lowercase());
+
+        match connection.as_deref() {
+            Some(value) if value.contains("close") => false,
+            Some(value) if value.contains("keep-alive") => true,
+            // HTTP/1.1 connections are persistent by default, HTTP/1.0 ones are not
+            _ => self.http_version != "HTTP/1.0"
+        }
+    }
+
+    pub fn get_header(&self, name: &str) -> Option<&String> {
+        self.headers.iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    // The data of the body, along with its raw bytes unless it was streamed to disk
+    fn extract_request_data<R: Read>(body_reader: &mut R, content_type: &str, uploads: &UploadConfig) -> Result<(RequestData, Vec<u8>), ReadError> {
+        // Parameters follow the media type, e.g. `multipart/form-data; boundary=...` or `application/json; charset=utf-8`
+        let media_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+
+        if media_type == "multipart/form-data" {
+            return Ok((Form(FormData::from_multipart(body_reader, content_type, uploads)?), Vec::new()));
+        }
+
+        let mut body = Vec::new();
+        body_reader.read_to_end(&mut body)?;
+
+        Ok((Self::parse_body(&body, &media_type)?, body))
+    }
+
+    fn parse_body(body: &[u8], media_type: &str) -> Result<RequestData, String> {
+        if media_type == "application/x-www-form-urlencoded" {
+            return Ok(Form(FormData::from_urlencoded(body)?));
+        }
+
+        // Structured syntax suffixes like `application/problem+json` are JSON too
+        if media_type == "application/json" || media_type.ends_with("+json") {
+            if let Ok(value) = serde_json::from_slice(body) {
+                return Ok(Json(value));
+            }
+        }
+
+        if media_type.starts_with("text/") {
+            return Ok(Text(String::from_utf8_lossy(body).to_string()));
+        }
 
-        Text(String::new())
+        Ok(Text(String::new()))
     }
 }
\ No newline at end of file
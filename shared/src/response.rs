@@ -1,35 +1,110 @@
 use std::collections::HashMap;
-use std::{env, fs};
+use std::{fs, io};
+use std::fmt::{Debug, Formatter};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use serde::Serialize;
-use crate::constants::{CONTENT_TYPE_HEADER, CONTENT_TYPE_MAP, DEFAULT_CONTENT_TYPE};
+use crate::constants::{ACCEPT_RANGES_HEADER, BINARY_CONTENT_TYPE, CONTENT_LENGTH_HEADER, CONTENT_RANGE_HEADER, CONTENT_TYPE_HEADER, TRANSFER_ENCODING_HEADER, CONTENT_TYPE_MAP, DEFAULT_CONTENT_TYPE, DEFAULT_STATUS_CODE, ETAG_HEADER, LAST_MODIFIED_HEADER, SET_COOKIE_HEADER, STATUS_CODES_MAP};
+use crate::cookie::{Cookie, CookieJar};
+use crate::error::TemplateError;
+use crate::http_date::HttpDate;
+use crate::range::{ByteRange, RangesReader};
+use crate::template::{resolve_view_path, TemplateRegistry, TemplateSource};
+
+const CRLF: &str = "\r\n";
+
+// Size of the chunks read from a streamed body
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// What is sent after the headers.
+/// Bytes, files and readers have a known `Content-Length`, streams are sent with `Transfer-Encoding: chunked`
+pub enum ResponseBody {
+    Bytes(Vec<u8>),
+    File(fs::File, u64),
+    Reader(Box<dyn Read + Send>, u64),
+    Stream(Box<dyn Read + Send>)
+}
 
 #[derive(Debug)]
 pub struct Response {
     status: usize,
-    data: String,
+    body: ResponseBody,
     headers: HashMap<String, String>,
-    cookies: HashMap<String, String>
+    cookies: CookieJar,
+    templates: Option<Arc<TemplateRegistry>>,
+    // HTTP/1.0 clients do not know the chunked encoding
+    chunked: bool
+}
+
+// Reads the chunks of an iterator one after the other
+struct ChunksReader<I: Iterator<Item = Vec<u8>>> {
+    chunks: I,
+    current: Vec<u8>,
+    position: usize
 }
 
 pub type ResponseResult = Result<Response, String>;
 
 
+impl Default for Response {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Response {
     pub fn new() -> Self {
         Response {
             status: 200,
-            data: String::from(""),
+            body: ResponseBody::Bytes(Vec::new()),
             headers: HashMap::new(),
-            cookies: HashMap::new()
+            cookies: CookieJar::new(),
+            templates: None,
+            chunked: true
         }
     }
 
     pub fn get_status(&self) -> usize {
-        self.status.clone()
+        self.status
     }
 
     pub fn set_cookie(&mut self, key: String, value: String) {
-        self.cookies.insert(key, value);
+        self.cookies.add(Cookie::new(key, value));
+    }
+
+    pub fn add_cookie(&mut self, cookie: Cookie) {
+        self.cookies.add(cookie);
+    }
+
+    pub fn cookies(&mut self) -> &mut CookieJar {
+        &mut self.cookies
+    }
+
+    /// Render views from the registry rather than reading them from disk
+    pub fn set_templates(&mut self, templates: Arc<TemplateRegistry>) {
+        self.templates = Some(templates);
+    }
+
+    /// Whether streams can be sent chunked, otherwise they are sent as is and end with the connection
+    pub fn set_chunked(&mut self, chunked: bool) {
+        self.chunked = chunked;
+    }
+
+    /// Whether only closing the connection tells the client where the body ends
+    pub fn is_close_delimited(&self) -> bool {
+        !self.chunked && self.has_body() && self.get_content_length().is_none()
+    }
+
+    pub fn get_header(&self, key: &str) -> Option<&String> {
+        self.headers.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    pub fn get_cookie(&self, key: &str) -> Option<&Cookie> {
+        self.cookies.get(key)
     }
 
     pub fn set_header(&mut self, key: String, value: String) {
@@ -42,7 +117,7 @@ impl Response {
     }
     pub fn text(&mut self, data: String) -> &Self {
         self.headers.insert(String::from(CONTENT_TYPE_HEADER), String::from(DEFAULT_CONTENT_TYPE));
-        self.data = data;
+        self.set_data(data);
 
         self
     }
@@ -54,13 +129,13 @@ impl Response {
                 if let Some(&content_type) = CONTENT_TYPE_MAP.get("json") {
                     self.headers.insert(String::from(CONTENT_TYPE_HEADER), String::from(content_type));
                 }
-                self.data = data;
+                self.set_data(data);
             },
             Err(err) => {
                 let type_name = std::any::type_name::<Option<T>>();
                 let message = format!("Serialization of {:?} Failed: {:?}", type_name, err.to_string());
 
-                self.data = message;
+                self.set_data(message);
                 self.status(500);
             }
         };
@@ -68,54 +143,437 @@ impl Response {
         self
     }
 
+    /// Send a view as is, read from the views directory of the registry
     pub fn view(&mut self, file_name: &str) -> &Self {
-        let current_dir = env::current_dir().unwrap();
-        let mut path = current_dir.as_path().join("src/views");
+        let path = match &self.templates {
+            Some(templates) => resolve_view_path(templates.get_root(), file_name),
+            None => return self.missing_templates()
+        };
 
-        let mut file_name = String::from(file_name);
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                if let Some(&content_type) = CONTENT_TYPE_MAP.get("html") {
+                    self.headers.insert(String::from(CONTENT_TYPE_HEADER), String::from(content_type));
+                }
 
-        path = path.join(file_name.clone());
+                if let Ok(metadata) = fs::metadata(&path) {
+                    self.set_validators(&metadata);
+                }
 
-        if path.is_dir() {
-            path = path.join("index.html");
-        } else {
-            if !file_name.ends_with(".html") {
-                path.set_extension("html");
+                self.set_data(content);
+            },
+            Err(err) => {
+                match err.kind() {
+                    io::ErrorKind::PermissionDenied => {
+                        self.set_data(String::from("Access Denied"));
+                        self.status(500);
+                    },
+                    _ => {
+                        self.set_data(String::from("Not Found"));
+                        self.status(404);
+                    }
+                }
             }
+        };
+
+        self
+    }
+
+    /// Render a view template with a context, see `Template` for the syntax
+    pub fn render<T: Serialize>(&mut self, name: &str, context: &T) -> &Self {
+        match self.templates.clone() {
+            Some(templates) => self.render_with(templates.as_ref(), name, context),
+            None => self.missing_templates()
         }
+    }
+
+    // Responses built outside of the server have no registry, they render with `render_with`
+    fn missing_templates(&mut self) -> &Self {
+        self.status(500);
+        self.text(String::from("No Views Directory Configured"))
+    }
 
-        print!("{:?}", path);
+    /// Render a view template loaded from the given source
+    pub fn render_with<T: Serialize>(&mut self, source: &dyn TemplateSource, name: &str, context: &T) -> &Self {
+        let context = match serde_json::to_value(context) {
+            Ok(context) => context,
+            Err(err) => {
+                self.status(500);
+                return self.text(format!("Serialization of the context of {:?} Failed: {:?}", name, err.to_string()));
+            }
+        };
 
-        match fs::read_to_string(path) {
+        match source.get_template(name).and_then(|template| template.render(&context, source)) {
             Ok(content) => {
                 if let Some(&content_type) = CONTENT_TYPE_MAP.get("html") {
                     self.headers.insert(String::from(CONTENT_TYPE_HEADER), String::from(content_type));
                 }
 
-                self.data = content;
+                self.set_data(content);
+            },
+            // A missing partial or layout is a server error, only the view itself can be missing
+            Err(TemplateError::NotFound(missing)) if missing == name => {
+                self.status(404);
+                self.text(String::from("Not Found"));
+            },
+            Err(err) => {
+                self.status(500);
+                self.text(err.to_string());
+            }
+        }
+
+        self
+    }
+
+    /// Send raw bytes, as `application/octet-stream` unless a content type was set before
+    pub fn bytes(&mut self, data: Vec<u8>) -> &Self {
+        if self.get_header(CONTENT_TYPE_HEADER).is_none() {
+            self.headers.insert(String::from(CONTENT_TYPE_HEADER), String::from(BINARY_CONTENT_TYPE));
+        }
+
+        self.body = ResponseBody::Bytes(data);
+        self
+    }
+
+    /// Send a file from disk without loading it in memory, its content type is guessed from its extension
+    pub fn file<P: AsRef<Path>>(&mut self, path: P) -> &Self {
+        let path = path.as_ref();
+
+        let file = fs::File::open(path).and_then(|file| {
+            let metadata = file.metadata()?;
+
+            if metadata.is_dir() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "Not a file"));
+            }
+
+            Ok((file, metadata))
+        });
+
+        match file {
+            Ok((file, metadata)) => {
+                let content_type = path.extension()
+                    .and_then(|extension| CONTENT_TYPE_MAP.get(extension.to_string_lossy().to_lowercase().as_str()))
+                    .unwrap_or(&BINARY_CONTENT_TYPE);
+
+                self.headers.insert(String::from(CONTENT_TYPE_HEADER), String::from(*content_type));
+                self.set_validators(&metadata);
+                self.headers.insert(String::from(ACCEPT_RANGES_HEADER), String::from("bytes"));
+                self.body = ResponseBody::File(file, metadata.len());
             },
             Err(err) => {
                 match err.kind() {
-                    std::io::ErrorKind::PermissionDenied => {
-                        self.data = String::from("Access Denied");
-                        self.status(500);
+                    io::ErrorKind::PermissionDenied => {
+                        self.status(403);
+                        self.text(String::from("Access Denied"));
                     },
                     _ => {
-                        self.data = String::from("Not Found");
                         self.status(404);
+                        self.text(String::from("Not Found"));
                     }
                 }
             }
+        }
+
+        self
+    }
+
+    /// Stream the body from a reader, it is sent in chunks as it is read
+    pub fn stream<R>(&mut self, reader: R) -> &Self
+    where R: Read + Send + 'static {
+        self.body = ResponseBody::Stream(Box::new(reader));
+        self
+    }
+
+    /// Stream the body from an iterator, each item is sent as soon as it is produced
+    pub fn stream_chunks<I>(&mut self, chunks: I) -> &Self
+    where I: Iterator + Send + 'static, I::Item: Into<Vec<u8>> {
+        let reader = ChunksReader {
+            chunks: chunks.map(Into::into),
+            current: Vec::new(),
+            position: 0
         };
 
+        self.stream(reader)
+    }
+
+    /// Answer with `304 Not Modified`, keeping the headers but dropping the body
+    pub fn not_modified(&mut self) -> &Self {
+        self.status(304);
+        self.body = ResponseBody::Bytes(Vec::new());
         self
     }
 
-    pub fn get_data(&self) -> &String {
-        &self.data
+    /// Narrow the body down to the ranges with a `206 Partial Content`,
+    /// several ranges are sent as a `multipart/byteranges` body
+    pub fn partial_content(&mut self, ranges: &[ByteRange]) -> &Self {
+        let Some(length) = self.get_range_length() else {
+            return self;
+        };
+
+        let body = std::mem::replace(&mut self.body, ResponseBody::Bytes(Vec::new()));
+
+        let result = match ranges {
+            [range] => {
+                self.headers.insert(String::from(CONTENT_RANGE_HEADER), format!("bytes {}-{}/{}", range.start, range.end, length));
+                Self::single_range(body, range)
+            },
+            ranges => self.multiple_ranges(body, ranges, length)
+        };
+
+        match result {
+            Ok(body) => {
+                self.body = body;
+                self.status(206)
+            },
+            Err(err) => {
+                self.headers.remove(CONTENT_RANGE_HEADER);
+                self.status(500);
+                self.text(err.to_string())
+            }
+        }
+    }
+
+    /// None of the requested ranges overlap the `length` bytes of the body
+    pub fn range_not_satisfiable(&mut self, length: u64) -> &Self {
+        self.headers.insert(String::from(CONTENT_RANGE_HEADER), format!("bytes */{}", length));
+        self.body = ResponseBody::Bytes(Vec::new());
+        self.status(416)
+    }
+
+    /// The length of a body that can be split into ranges
+    pub fn get_range_length(&self) -> Option<u64> {
+        match &self.body {
+            ResponseBody::Bytes(data) => Some(data.len() as u64),
+            ResponseBody::File(_, length) => Some(*length),
+            _ => None
+        }
+    }
+
+    fn single_range(body: ResponseBody, range: &ByteRange) -> io::Result<ResponseBody> {
+        match body {
+            ResponseBody::Bytes(data) => Ok(ResponseBody::Bytes(data[range.start as usize..=range.end as usize].to_vec())),
+            ResponseBody::File(mut file, _) => {
+                file.seek(SeekFrom::Start(range.start))?;
+                Ok(ResponseBody::File(file, range.length()))
+            },
+            body => Ok(body)
+        }
+    }
+
+    fn multiple_ranges(&mut self, body: ResponseBody, ranges: &[ByteRange], length: u64) -> io::Result<ResponseBody> {
+        let content_type = Self::sanitize(&self.get_content_type());
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let boundary = format!("wrust-byteranges-{:x}", since_epoch.as_nanos());
+
+        let parts = ranges.iter().enumerate()
+            .map(|(index, range)| {
+                let header = format!(
+                    "{}--{boundary}{CRLF}{}: {}{CRLF}{}: bytes {}-{}/{}{CRLF}{CRLF}",
+                    if index == 0 { "" } else { CRLF },
+                    CONTENT_TYPE_HEADER, content_type,
+                    CONTENT_RANGE_HEADER, range.start, range.end, length
+                );
+
+                (header.into_bytes(), *range)
+            })
+            .collect::<Vec<(Vec<u8>, ByteRange)>>();
+        let closing = format!("{CRLF}--{boundary}--{CRLF}").into_bytes();
+
+        let total = parts.iter().map(|(header, range)| header.len() as u64 + range.length()).sum::<u64>() + closing.len() as u64;
+
+        let reader: Box<dyn Read + Send> = match body {
+            ResponseBody::Bytes(data) => Box::new(RangesReader::new(Cursor::new(data), parts, closing)),
+            ResponseBody::File(file, _) => Box::new(RangesReader::new(file, parts, closing)),
+            body => return Ok(body)
+        };
+
+        self.headers.insert(String::from(CONTENT_TYPE_HEADER), format!("multipart/byteranges; boundary={}", boundary));
+        Ok(ResponseBody::Reader(reader, total))
+    }
+
+    // `ETag` and `Last-Modified` of a file, from its size and modification time
+    fn set_validators(&mut self, metadata: &fs::Metadata) {
+        let Ok(modified) = metadata.modified() else {
+            return;
+        };
+
+        let modified_since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let etag = format!("\"{:x}-{:x}\"", metadata.len(), modified_since_epoch.as_nanos());
+
+        self.headers.insert(String::from(ETAG_HEADER), etag);
+        self.headers.insert(String::from(LAST_MODIFIED_HEADER), HttpDate::format(modified));
+    }
+
+    /// Whether the status allows a body, 1xx, 204 and 304 responses never have one
+    pub fn has_body(&self) -> bool {
+        !matches!(self.status, 100..=199 | 204 | 304)
+    }
+
+    fn set_data(&mut self, data: String) {
+        self.body = ResponseBody::Bytes(data.into_bytes());
+    }
+
+    pub fn get_body(&self) -> &ResponseBody {
+        &self.body
+    }
+
+    /// The length of the body, unknown for streams
+    pub fn get_content_length(&self) -> Option<u64> {
+        match &self.body {
+            ResponseBody::Bytes(data) => Some(data.len() as u64),
+            ResponseBody::File(_, length) => Some(*length),
+            ResponseBody::Reader(_, length) => Some(*length),
+            ResponseBody::Stream(_) => None
+        }
     }
 
     pub fn get_content_type(&self) -> String {
-        self.headers.get(CONTENT_TYPE_HEADER).unwrap_or(&DEFAULT_CONTENT_TYPE.to_string()).to_string()
+        self.get_header(CONTENT_TYPE_HEADER).unwrap_or(&DEFAULT_CONTENT_TYPE.to_string()).to_string()
+    }
+
+    pub fn get_status_description(&self) -> String {
+        match STATUS_CODES_MAP.get(&self.status) {
+            Some(&result) => {
+                String::from(result)
+            },
+            None => {
+                let mut result = DEFAULT_STATUS_CODE;
+
+                if self.status > 199 && self.status < 600 {
+                    let status_code = (self.status / 100) * 100;
+
+                    if let Some(&description) = STATUS_CODES_MAP.get(&status_code) {
+                        result = description;
+                    }
+                }
+
+                String::from(result)
+            }
+        }
+    }
+
+    /// Serialize the status line, headers, cookies and body to the writer.
+    /// For HEAD requests the body is left out but its length is still advertised.
+    pub fn write_to<W: Write>(&mut self, writer: &mut W, is_head: bool) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}{CRLF}", self.status, self.get_status_description());
+        let has_body = self.has_body();
+
+        if has_body {
+            match self.get_content_length() {
+                Some(length) => head.push_str(&format!("{}: {}{CRLF}", CONTENT_LENGTH_HEADER, length)),
+                None if self.chunked => head.push_str(&format!("{}: chunked{CRLF}", TRANSFER_ENCODING_HEADER)),
+                None => ()
+            }
+
+            head.push_str(&format!("{}: {}{CRLF}", CONTENT_TYPE_HEADER, Self::sanitize(&self.get_content_type())));
+        }
+
+        let mut headers = self.headers.iter()
+            .filter(|(name, _)| {
+                ![CONTENT_LENGTH_HEADER, CONTENT_TYPE_HEADER, TRANSFER_ENCODING_HEADER].iter().any(|header| name.eq_ignore_ascii_case(header))
+            })
+            .collect::<Vec<(&String, &String)>>();
+        headers.sort();
+
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}{CRLF}", Self::sanitize(name), Self::sanitize(value)));
+        }
+
+        let mut cookies = self.cookies.iter().collect::<Vec<&Cookie>>();
+        cookies.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for cookie in cookies {
+            head.push_str(&format!("{}: {}{CRLF}", SET_COOKIE_HEADER, Self::sanitize(&cookie.to_header_value())));
+        }
+
+        head.push_str(CRLF);
+
+        writer.write_all(head.as_bytes())?;
+
+        if is_head || !has_body {
+            return writer.flush();
+        }
+
+        match &mut self.body {
+            ResponseBody::Bytes(data) => writer.write_all(data)?,
+            ResponseBody::File(file, length) => Self::write_sized(file, *length, writer)?,
+            ResponseBody::Reader(reader, length) => Self::write_sized(reader, *length, writer)?,
+            ResponseBody::Stream(reader) if self.chunked => Self::write_chunks(reader, writer)?,
+            ResponseBody::Stream(reader) => {
+                io::copy(reader, writer)?;
+            }
+        }
+
+        writer.flush()
+    }
+
+    // The file may have changed since it was opened, never send more than announced
+    fn write_sized<R: Read, W: Write>(reader: &mut R, length: u64, writer: &mut W) -> io::Result<()> {
+        let copied = io::copy(&mut reader.take(length), writer)?;
+
+        if copied < length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Body shorter than announced"));
+        }
+
+        Ok(())
+    }
+
+    fn write_chunks<W: Write>(reader: &mut Box<dyn Read + Send>, writer: &mut W) -> io::Result<()> {
+        let mut buffer = vec![0; CHUNK_SIZE];
+
+        loop {
+            let size = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => size,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err)
+            };
+
+            writer.write_all(format!("{:X}{CRLF}", size).as_bytes())?;
+            writer.write_all(&buffer[..size])?;
+            writer.write_all(CRLF.as_bytes())?;
+
+            // Incremental bodies are only useful if each chunk reaches the client right away
+            writer.flush()?;
+        }
+
+        writer.write_all(format!("0{CRLF}{CRLF}").as_bytes())
+    }
+
+    // Line breaks in a header would let a value inject headers of its own
+    fn sanitize(value: &str) -> String {
+        value.chars().filter(|ch| *ch != '\r' && *ch != '\n').collect()
+    }
+}
+
+impl Debug for ResponseBody {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResponseBody::Bytes(data) => write!(f, "Bytes({} bytes)", data.len()),
+            ResponseBody::File(_, length) => write!(f, "File({} bytes)", length),
+            ResponseBody::Reader(_, length) => write!(f, "Reader({} bytes)", length),
+            ResponseBody::Stream(_) => f.write_str("Stream")
+        }
+    }
+}
+
+impl<I: Iterator<Item = Vec<u8>>> Read for ChunksReader<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Skip the empty chunks, an empty read would end the body
+        while self.position >= self.current.len() {
+            match self.chunks.next() {
+                Some(chunk) => {
+                    self.current = chunk;
+                    self.position = 0;
+                },
+                None => return Ok(0)
+            }
+        }
+
+        let size = buf.len().min(self.current.len() - self.position);
+        buf[..size].copy_from_slice(&self.current[self.position..self.position + size]);
+        self.position += size;
+
+        Ok(size)
     }
 }
\ No newline at end of file
@@ -2,73 +2,236 @@ use std::collections::HashMap;
 use std::fmt::{Debug};
 use std::sync::{Arc, RwLock};
 use regex::Regex;
+use crate::middleware::Middleware;
 use crate::query::{Flags, QueriesHashMap, QueryParamType};
 use crate::query::QueryParamValueType::{Boolean, Float, Int, Str, UInt};
-use crate::request::{Request};
+use crate::request::Request;
 use crate::response::Response;
+use crate::validation::Rule;
 
 pub type Handler = dyn Fn(Request, &mut Response) -> &Response + Sync + Send;
 pub type Controller = Arc<RwLock<Box<Handler>>>;
-pub type RoutesHashMap = HashMap<String, Route>;
-pub type MethodsHashMap = HashMap<RouteMethod, RoutesHashMap>;
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum RouteMethod {
     RouteGet,
     RoutePost,
+    RoutePut,
+    RoutePatch,
+    RouteDelete,
+    RouteHead,
+    RouteOptions,
+    RouteCustom(String),
     RouteAny
 }
 
+impl RouteMethod {
+    /// The methods a `RouteAny` route answers to, besides extension methods
+    pub const STANDARD: [RouteMethod; 7] = [
+        RouteMethod::RouteGet,
+        RouteMethod::RoutePost,
+        RouteMethod::RoutePut,
+        RouteMethod::RoutePatch,
+        RouteMethod::RouteDelete,
+        RouteMethod::RouteHead,
+        RouteMethod::RouteOptions
+    ];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            RouteMethod::RouteGet => "GET",
+            RouteMethod::RoutePost => "POST",
+            RouteMethod::RoutePut => "PUT",
+            RouteMethod::RoutePatch => "PATCH",
+            RouteMethod::RouteDelete => "DELETE",
+            RouteMethod::RouteHead => "HEAD",
+            RouteMethod::RouteOptions => "OPTIONS",
+            RouteMethod::RouteCustom(method) => method.as_str(),
+            RouteMethod::RouteAny => "*"
+        }
+    }
+}
+
+// A path segment of a route pattern: `/people`, `/:id:uint` or `/*rest`
+#[derive(Debug, Clone)]
+pub enum PathSegment {
+    Static(String),
+    Param(String, QueryParamType),
+    Wildcard(String)
+}
+
+// What a route is about, for listing routes or finding one by name
+#[derive(Debug, Clone, Default)]
+pub struct RouteMetadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>
+}
+
 pub struct Route
 {
+    pub path: String,
     pub queries: QueriesHashMap,
-    pub controller: Controller
+    // Fields expected in a form body, declared like queries: `name&age:uint&tags+`
+    pub body: QueriesHashMap,
+    pub segments: Vec<PathSegment>,
+    pub controller: Controller,
+    pub middlewares: Vec<Arc<dyn Middleware>>,
+    pub metadata: RouteMetadata
 }
 
 impl Route {
-    pub fn new(path: String, handler: Box<Handler>) -> (Route, String) {
-        let (queries, path) = Route::generate_queries(path);
+    /// Build a route from its pattern, failing when the validation rules of a param are invalid
+    pub fn new(path: String, handler: Box<Handler>) -> Result<(Route, String), String> {
+        let (queries, path) = Route::generate_queries(path)?;
+        let segments = Route::generate_segments(&path)?;
         let controller = Arc::new(RwLock::new(handler));
 
         let route = Route {
+            path: path.clone(),
             queries,
-            controller
+            body: HashMap::new(),
+            segments,
+            controller,
+            middlewares: Vec::new(),
+            metadata: RouteMetadata::default()
+        };
+
+        Ok((route, path))
+    }
+
+    /// The specs of the path params, to validate them like queries
+    pub fn get_params(&self) -> QueriesHashMap {
+        self.segments.iter()
+            .filter_map(|segment| match segment {
+                PathSegment::Param(name, param_type) => Some((name.clone(), param_type.clone())),
+                _ => None
+            })
+            .collect()
+    }
+
+    pub fn generate_segments(path: &str) -> Result<Vec<PathSegment>, String> {
+        let mut segments = Vec::new();
+
+        for part in Self::split_path(path) {
+            if let Some(name) = part.strip_prefix(':') {
+                let (name, rules) = Self::extract_rules(name)?;
+                let (name, mut param_type) = match name.split_once(':') {
+                    Some((name, data_type)) => (name, Self::extract_param_type(data_type)),
+                    None => (name, Self::extract_param_type(""))
+                };
+
+                param_type.rules = rules;
+
+                segments.push(PathSegment::Param(name.trim().to_string(), param_type));
+            } else if let Some(name) = part.strip_prefix('*') {
+                let name = if name.is_empty() { "*" } else { name.trim() };
+
+                // Anything after a wildcard can never be reached
+                segments.push(PathSegment::Wildcard(name.to_string()));
+                break;
+            } else {
+                segments.push(PathSegment::Static(part.to_string()));
+            }
+        }
+
+        Ok(segments)
+    }
+
+    fn split_path(path: &str) -> impl Iterator<Item = &str> {
+        Self::split_outside_groups(path, '/').into_iter().filter(|part| !part.is_empty())
+    }
+
+    pub fn generate_queries(path: String) -> Result<(QueriesHashMap, String), String> {
+        let Some(&index) = Self::find_separators(&path, '?').first() else {
+            return Ok((HashMap::new(), path));
         };
 
-        (route, path)
+        let (specs, path) = (&path[index + 1..], &path[..index]);
+
+        // `/people/:id?` is not a param followed by queries, and path segments can not be optional
+        if let Some(param) = Self::split_path(path).last().filter(|part| part.starts_with(':')) {
+            if specs.is_empty() || specs.starts_with('/') {
+                return Err(format!("Optional path param {:?} is not supported, register the route with and without it", param));
+            }
+        }
+
+        Ok((Self::generate_params(specs)?, path.to_string()))
     }
 
-    pub fn generate_queries(path: String) -> (QueriesHashMap, String) {
+    /// Parse `&` separated param specs, e.g. `name?&age:uint|min=18&tags:str+`
+    pub fn generate_params(specs: &str) -> Result<QueriesHashMap, String> {
         let mut result = HashMap::new();
-        let mut clean_path = path.clone();
 
-        if let Some((path, query_string)) = path.clone().split_once("?") {
-            for param in query_string.split("&") {
-                let param_split = param.trim().split(":").collect::<Vec<&str>>();
+        for param in Self::split_outside_groups(specs, '&') {
+            let (param, rules) = Self::extract_rules(param)?;
+            let param_split = param.trim().split(":").collect::<Vec<&str>>();
 
-                if param_split.is_empty() {
-                    continue;
-                }
+            if param_split.is_empty() {
+                continue;
+            }
 
-                let name = param_split[0].trim();
+            let name = param_split[0].trim();
 
-                match param_split.get(1) {
-                    Some(data_type) => {
-                        let _type = Self::extract_param_type(*data_type);
+            match param_split.get(1) {
+                Some(data_type) => {
+                    let mut _type = Self::extract_param_type(data_type);
+                    _type.rules = rules;
 
-                        result.insert(name.to_string(), _type);
-                    }
-                    _ => {
-                        let (_type, name) = Self::extract_name_and_type(name);
-                        result.insert(name.to_string(), _type);
-                    }
+                    result.insert(name.to_string(), _type);
+                }
+                _ => {
+                    let (mut _type, name) = Self::extract_name_and_type(name);
+                    _type.rules = rules;
+
+                    result.insert(name.to_string(), _type);
                 }
             }
+        }
+
+        Ok(result)
+    }
+
+    fn split_outside_groups(spec: &str, separator: char) -> Vec<&str> {
+        let mut parts = Vec::new();
+        let mut start = 0;
 
-            clean_path = path.to_string();
+        for index in Self::find_separators(spec, separator) {
+            parts.push(&spec[start..index]);
+            start = index + separator.len_utf8();
         }
 
-        (result, clean_path)
+        parts.push(&spec[start..]);
+        parts
+    }
+
+    // Separators inside the `()`, `[]` or `{}` of a rule are part of it, so `regex=^(\d+/\d+)?$` stays whole.
+    // Escaped chars never open or close a group.
+    fn find_separators(spec: &str, separator: char) -> Vec<usize> {
+        let mut positions = Vec::new();
+        let mut depth = 0usize;
+        let mut in_class = false;
+        let mut escaped = false;
+
+        for (index, ch) in spec.char_indices() {
+            if escaped {
+                escaped = false;
+                continue;
+            }
+
+            match ch {
+                '\\' => escaped = true,
+                ']' if in_class => in_class = false,
+                _ if in_class => (),
+                '[' => in_class = true,
+                '(' | '{' => depth += 1,
+                ')' | '}' => depth = depth.saturating_sub(1),
+                _ if ch == separator && depth == 0 => positions.push(index),
+                _ => ()
+            }
+        }
+
+        positions
     }
 
     fn extract_param_type(_type: &str) -> QueryParamType {
@@ -84,7 +247,19 @@ impl Route {
 
         QueryParamType {
             flags,
-            _type
+            _type,
+            rules: Vec::new()
+        }
+    }
+
+    // Validation rules follow the name, type and flags of a param: `age:uint?|min=18|max=120`
+    fn extract_rules(spec: &str) -> Result<(&str, Vec<Rule>), String> {
+        match spec.split_once('|') {
+            Some((name, rules)) => {
+                let rules = Rule::parse_all(rules).map_err(|err| format!("Invalid param spec {:?}: {}", spec, err))?;
+                Ok((name, rules))
+            },
+            None => Ok((spec, Vec::new()))
         }
     }
 
@@ -93,7 +268,8 @@ impl Route {
 
         let _type = QueryParamType {
             flags,
-            _type: Str(String::new())
+            _type: Str(String::new()),
+            rules: Vec::new()
         };
 
         (_type, name)
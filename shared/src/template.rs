@@ -0,0 +1,836 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use serde_json::{Map, Value};
+use crate::error::TemplateError;
+
+pub const VIEW_EXTENSION: &str = "html";
+pub const VIEW_INDEX: &str = "index.html";
+
+// Includes and layouts deeper than this are most likely including themselves
+const MAX_DEPTH: usize = 32;
+
+/// Where templates are loaded from, by view name like `nested/test`
+pub trait TemplateSource {
+    fn get_template(&self, name: &str) -> Result<Arc<Template>, TemplateError>;
+}
+
+/// The views of a directory, read and parsed every time they are rendered
+pub struct ViewsDirectory {
+    root: PathBuf
+}
+
+impl ViewsDirectory {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        ViewsDirectory {
+            root: root.as_ref().to_path_buf()
+        }
+    }
+}
+
+impl TemplateSource for ViewsDirectory {
+    fn get_template(&self, name: &str) -> Result<Arc<Template>, TemplateError> {
+        Template::load(name, &resolve_view_path(&self.root, name)).map(Arc::new)
+    }
+}
+
+/// The parsed views of a directory, kept in memory between requests.
+/// With `hot_reload`, the modification time of a view is checked every time it is used
+/// and the view is parsed again when it changed, so templates can be edited without restarting the server.
+#[derive(Debug)]
+pub struct TemplateRegistry {
+    root: PathBuf,
+    hot_reload: bool,
+    templates: RwLock<HashMap<String, CachedTemplate>>
+}
+
+#[derive(Debug, Clone)]
+struct CachedTemplate {
+    template: Arc<Template>,
+    path: PathBuf,
+    modified: Option<SystemTime>
+}
+
+impl TemplateRegistry {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        TemplateRegistry {
+            root: root.as_ref().to_path_buf(),
+            hot_reload: false,
+            templates: RwLock::new(HashMap::new())
+        }
+    }
+
+    pub fn hot_reload(mut self, hot_reload: bool) -> Self {
+        self.hot_reload = hot_reload;
+        self
+    }
+
+    pub fn get_root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn is_hot_reload(&self) -> bool {
+        self.hot_reload
+    }
+
+    /// Parse every view of the directory, returning how many were loaded.
+    /// A missing directory has no views, a view with a syntax error fails the whole preload.
+    pub fn preload(&self) -> Result<usize, TemplateError> {
+        let mut files = Vec::new();
+        Self::find_views(&self.root, &mut files).map_err(|err| TemplateError::Io(self.root.display().to_string(), err))?;
+
+        let count = files.len();
+        let mut templates = HashMap::new();
+
+        for path in files {
+            let Ok(relative) = path.strip_prefix(&self.root) else {
+                continue;
+            };
+
+            let name = relative.with_extension("").to_string_lossy().replace('\\', "/");
+            let cached = Self::load(&name, path.clone())?;
+
+            // `nested/index` is also reachable as `nested`
+            if let Some(directory) = name.strip_suffix("/index").or(if name == "index" { Some("") } else { None }) {
+                templates.insert(directory.to_string(), cached.clone());
+            }
+
+            templates.insert(name, cached);
+        }
+
+        if let Ok(mut cache) = self.templates.write() {
+            *cache = templates;
+        }
+
+        Ok(count)
+    }
+
+    fn find_views(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err)
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                Self::find_views(&path, files)?;
+            } else if path.extension().is_some_and(|extension| extension == VIEW_EXTENSION) {
+                files.push(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load(name: &str, path: PathBuf) -> Result<CachedTemplate, TemplateError> {
+        // The time is read first, a change made while parsing is picked up by the next check
+        let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+        let template = Template::load(name, &path)?;
+
+        Ok(CachedTemplate { template: Arc::new(template), path, modified })
+    }
+
+    fn is_outdated(cached: &CachedTemplate) -> bool {
+        let modified = fs::metadata(&cached.path).and_then(|metadata| metadata.modified()).ok();
+        modified != cached.modified
+    }
+}
+
+impl TemplateSource for TemplateRegistry {
+    fn get_template(&self, name: &str) -> Result<Arc<Template>, TemplateError> {
+        let cached = self.templates.read().ok().and_then(|templates| templates.get(name).cloned());
+
+        if let Some(cached) = &cached {
+            if !self.hot_reload || !Self::is_outdated(cached) {
+                return Ok(Arc::clone(&cached.template));
+            }
+        }
+
+        // Views added after the preload are loaded on their first use
+        let path = cached.map(|cached| cached.path).unwrap_or_else(|| resolve_view_path(&self.root, name));
+        let loaded = Self::load(name, path)?;
+        let template = Arc::clone(&loaded.template);
+
+        if let Ok(mut templates) = self.templates.write() {
+            templates.insert(name.to_string(), loaded);
+        }
+
+        Ok(template)
+    }
+}
+
+/// The file of a view: directories resolve to their `index.html`, and `.html` is added when missing
+pub fn resolve_view_path(root: &Path, name: &str) -> PathBuf {
+    let mut path = root.join(name.trim_start_matches('/'));
+
+    if path.is_dir() {
+        path = path.join(VIEW_INDEX);
+    } else if !name.ends_with(&format!(".{}", VIEW_EXTENSION)) {
+        path.set_extension(VIEW_EXTENSION);
+    }
+
+    path
+}
+
+/// A parsed template.
+///
+/// - `{{ user.name }}` outputs an HTML escaped value, `{{ html | raw }}` outputs it as is,
+///   `upper`, `lower` and `length` filters are also available
+/// - `{% if a %}`, `{% elif not b %}`, `{% else %}`, `{% endif %}`, conditions support `==`, `!=`, `and` and `or`
+/// - `{% for item in items %}`, `{% for key, value in object %}`, with `loop.index`, `loop.first`, `loop.last`,
+///   and an optional `{% else %}` for empty collections
+/// - `{% include "partials/header" %}` renders another view with the current variables
+/// - `{% extends "layout" %}` renders a layout, replacing its `{% block name %}...{% endblock %}` with the ones of the view
+/// - `{# comments #}` are left out
+#[derive(Debug)]
+pub struct Template {
+    name: String,
+    nodes: Vec<Node>,
+    extends: Option<String>,
+    blocks: HashMap<String, Vec<Node>>
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Output(Expression, Vec<Filter>),
+    If(Vec<(Condition, Vec<Node>)>, Vec<Node>),
+    For(Option<String>, String, Expression, Vec<Node>, Vec<Node>),
+    Include(String),
+    Block(String)
+}
+
+#[derive(Debug)]
+enum Expression {
+    Path(Vec<String>),
+    Literal(Value)
+}
+
+#[derive(Debug)]
+enum Condition {
+    Value(Expression),
+    Not(Box<Condition>),
+    Equals(Expression, Expression),
+    NotEquals(Expression, Expression),
+    And(Vec<Condition>),
+    Or(Vec<Condition>)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Filter {
+    Raw,
+    Upper,
+    Lower,
+    Length
+}
+
+// Nodes parsed up to an end tag, returned with the line of the tag
+type ParsedNodes = (Vec<Node>, Option<(String, usize)>);
+
+#[derive(Clone)]
+enum Token {
+    Text(String),
+    Output(String, usize),
+    Tag(String, usize)
+}
+
+impl Template {
+    pub fn parse(name: &str, source: &str) -> Result<Template, TemplateError> {
+        let tokens = Self::tokenize(name, source)?;
+        let mut parser = Parser { name, tokens, position: 0, blocks: HashMap::new(), extends: None };
+
+        let (nodes, end) = parser.parse_nodes(&[])?;
+
+        if let Some((tag, line)) = end {
+            return Err(TemplateError::Syntax(name.to_string(), line, format!("Unexpected {{% {} %}}", tag)));
+        }
+
+        Ok(Template {
+            name: name.to_string(),
+            nodes,
+            extends: parser.extends,
+            blocks: parser.blocks
+        })
+    }
+
+    /// Read and parse a template file
+    pub fn load(name: &str, path: &Path) -> Result<Template, TemplateError> {
+        match fs::read_to_string(path) {
+            Ok(source) => Self::parse(name, &source),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(TemplateError::NotFound(name.to_string())),
+            Err(err) => Err(TemplateError::Io(name.to_string(), err))
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn render(&self, context: &Value, source: &dyn TemplateSource) -> Result<String, TemplateError> {
+        let root = match context {
+            Value::Object(map) => map.clone(),
+            _ => Map::new()
+        };
+
+        // The layouts this template extends, the outermost one is rendered
+        let mut chain = Vec::new();
+        let mut extends = self.extends.clone();
+
+        while let Some(layout) = extends {
+            if chain.len() >= MAX_DEPTH {
+                return Err(TemplateError::Render(self.name.clone(), String::from("Too many nested layouts")));
+            }
+
+            let layout = source.get_template(&layout)?;
+            extends = layout.extends.clone();
+            chain.push(layout);
+        }
+
+        // The blocks of the most derived template win
+        let mut blocks = HashMap::new();
+        for template in chain.iter().map(|template| template.as_ref()).rev().chain([self]) {
+            for (name, nodes) in &template.blocks {
+                blocks.insert(name.clone(), nodes.as_slice());
+            }
+        }
+
+        let base = chain.last().map(|template| template.as_ref()).unwrap_or(self);
+        let mut renderer = Renderer { source, scopes: Vec::from([root]), blocks, depth: 0 };
+        let mut output = String::new();
+
+        renderer.render_nodes(base, &base.nodes, &mut output)?;
+
+        Ok(output)
+    }
+
+    fn tokenize(name: &str, source: &str) -> Result<Vec<Token>, TemplateError> {
+        let mut tokens = Vec::new();
+        let mut rest = source;
+        let mut line = 1;
+
+        while let Some(start) = rest.find('{') {
+            let closing = match rest[start + 1..].chars().next() {
+                Some('{') => "}}",
+                Some('%') => "%}",
+                Some('#') => "#}",
+                _ => {
+                    let text = &rest[..start + 1];
+                    Self::push_text(&mut tokens, text);
+                    line += text.matches('\n').count();
+                    rest = &rest[start + 1..];
+                    continue;
+                }
+            };
+
+            let text = &rest[..start];
+            Self::push_text(&mut tokens, text);
+            line += text.matches('\n').count();
+
+            let Some(end) = rest[start + 2..].find(closing) else {
+                return Err(TemplateError::Syntax(name.to_string(), line, format!("Missing closing {:?}", closing)));
+            };
+
+            let content = &rest[start + 2..start + 2 + end];
+
+            match closing {
+                "}}" => tokens.push(Token::Output(content.trim().to_string(), line)),
+                "%}" => tokens.push(Token::Tag(content.trim().to_string(), line)),
+                _ => ()
+            }
+
+            line += content.matches('\n').count();
+            rest = &rest[start + 2 + end + 2..];
+        }
+
+        Self::push_text(&mut tokens, rest);
+
+        Ok(tokens)
+    }
+
+    fn push_text(tokens: &mut Vec<Token>, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+
+        if let Some(Token::Text(previous)) = tokens.last_mut() {
+            previous.push_str(text);
+        } else {
+            tokens.push(Token::Text(text.to_string()));
+        }
+    }
+}
+
+struct Parser<'a> {
+    name: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+    blocks: HashMap<String, Vec<Node>>,
+    extends: Option<String>
+}
+
+impl Parser<'_> {
+    // Parse nodes until one of the end tags
+    fn parse_nodes(&mut self, ends: &[&str]) -> Result<ParsedNodes, TemplateError> {
+        let mut nodes = Vec::new();
+
+        while self.position < self.tokens.len() {
+            let token = self.tokens[self.position].clone();
+            self.position += 1;
+
+            match token {
+                Token::Text(text) => nodes.push(Node::Text(text)),
+                Token::Output(content, line) => {
+                    let (expression, filters) = self.parse_output(&content, line)?;
+                    nodes.push(Node::Output(expression, filters));
+                },
+                Token::Tag(content, line) => {
+                    let (keyword, arguments) = content.split_once(char::is_whitespace).unwrap_or((&content, ""));
+                    let arguments = arguments.trim();
+
+                    if ends.contains(&keyword) {
+                        return Ok((nodes, Some((content.clone(), line))));
+                    }
+
+                    let node = match keyword {
+                        "if" => self.parse_if(arguments, line)?,
+                        "for" => self.parse_for(arguments, line)?,
+                        "include" => Node::Include(self.parse_name(arguments, line)?),
+                        "block" => self.parse_block(arguments, line)?,
+                        "extends" => {
+                            // Only whitespace may come before `{% extends %}`
+                            let is_first = ends.is_empty() && nodes.iter().all(|node| matches!(node, Node::Text(text) if text.trim().is_empty()));
+
+                            if !is_first || self.extends.is_some() {
+                                return Err(self.error(line, String::from("{% extends %} must be the first tag of the template")));
+                            }
+
+                            self.extends = Some(self.parse_name(arguments, line)?);
+                            continue;
+                        },
+                        _ => return Err(self.error(line, format!("Unexpected {{% {} %}}", content)))
+                    };
+
+                    nodes.push(node);
+                }
+            }
+        }
+
+        Ok((nodes, None))
+    }
+
+    fn parse_if(&mut self, arguments: &str, line: usize) -> Result<Node, TemplateError> {
+        let mut branches = Vec::new();
+        let mut condition = self.parse_condition(arguments, line)?;
+
+        loop {
+            let (nodes, end) = self.parse_nodes(&["elif", "else", "endif"])?;
+            branches.push((condition, nodes));
+
+            let Some((end, end_line)) = end else {
+                return Err(self.error(line, String::from("Missing {% endif %}")));
+            };
+
+            match end.split_once(char::is_whitespace) {
+                Some(("elif", arguments)) => condition = self.parse_condition(arguments, end_line)?,
+                _ if end == "else" => {
+                    let (otherwise, end) = self.parse_nodes(&["endif"])?;
+
+                    if end.is_none() {
+                        return Err(self.error(line, String::from("Missing {% endif %}")));
+                    }
+
+                    return Ok(Node::If(branches, otherwise));
+                },
+                _ if end == "endif" => return Ok(Node::If(branches, Vec::new())),
+                _ => return Err(self.error(end_line, format!("Unexpected {{% {} %}}", end)))
+            }
+        }
+    }
+
+    fn parse_for(&mut self, arguments: &str, line: usize) -> Result<Node, TemplateError> {
+        let Some((variables, iterable)) = arguments.split_once(" in ") else {
+            return Err(self.error(line, String::from("Expected {% for item in items %}")));
+        };
+
+        let variables = variables.split(',').map(|name| name.trim()).collect::<Vec<&str>>();
+
+        if variables.iter().any(|name| !Self::is_identifier(name)) {
+            return Err(self.error(line, format!("Invalid loop variables: {:?}", arguments)));
+        }
+
+        let (key, value) = match variables.as_slice() {
+            [value] => (None, value.to_string()),
+            [key, value] => (Some(key.to_string()), value.to_string()),
+            _ => return Err(self.error(line, String::from("Expected one or two loop variables")))
+        };
+
+        let iterable = self.parse_expression(iterable, line)?;
+
+        let (body, end) = self.parse_nodes(&["else", "endfor"])?;
+
+        let otherwise = match end {
+            Some((end, _)) if end == "else" => {
+                let (otherwise, end) = self.parse_nodes(&["endfor"])?;
+
+                if end.is_none() {
+                    return Err(self.error(line, String::from("Missing {% endfor %}")));
+                }
+
+                otherwise
+            },
+            Some(_) => Vec::new(),
+            None => return Err(self.error(line, String::from("Missing {% endfor %}")))
+        };
+
+        Ok(Node::For(key, value, iterable, body, otherwise))
+    }
+
+    fn parse_block(&mut self, arguments: &str, line: usize) -> Result<Node, TemplateError> {
+        if !Self::is_identifier(arguments) {
+            return Err(self.error(line, format!("Invalid block name: {:?}", arguments)));
+        }
+
+        let (nodes, end) = self.parse_nodes(&["endblock"])?;
+
+        if end.is_none() {
+            return Err(self.error(line, String::from("Missing {% endblock %}")));
+        }
+
+        if self.blocks.contains_key(arguments) {
+            return Err(self.error(line, format!("Block {:?} is defined twice", arguments)));
+        }
+
+        // Blocks are rendered through the table so derived templates can replace them
+        self.blocks.insert(arguments.to_string(), nodes);
+
+        Ok(Node::Block(arguments.to_string()))
+    }
+
+    fn parse_name(&self, arguments: &str, line: usize) -> Result<String, TemplateError> {
+        match self.parse_expression(arguments, line)? {
+            Expression::Literal(Value::String(name)) => Ok(name),
+            _ => Err(self.error(line, format!("Expected a quoted template name, got {:?}", arguments)))
+        }
+    }
+
+    fn parse_output(&self, content: &str, line: usize) -> Result<(Expression, Vec<Filter>), TemplateError> {
+        let mut parts = Self::split_outside_quotes(content, "|").into_iter();
+        let expression = self.parse_expression(parts.next().unwrap_or_default(), line)?;
+
+        let filters = parts
+            .map(|filter| match filter.trim() {
+                "raw" => Ok(Filter::Raw),
+                "upper" => Ok(Filter::Upper),
+                "lower" => Ok(Filter::Lower),
+                "length" => Ok(Filter::Length),
+                filter => Err(self.error(line, format!("Unknown filter: {:?}", filter)))
+            })
+            .collect::<Result<Vec<Filter>, TemplateError>>()?;
+
+        Ok((expression, filters))
+    }
+
+    fn parse_condition(&self, content: &str, line: usize) -> Result<Condition, TemplateError> {
+        let alternatives = Self::split_outside_quotes(content, " or ");
+
+        if alternatives.len() > 1 {
+            let conditions = alternatives.into_iter()
+                .map(|alternative| self.parse_condition(alternative, line))
+                .collect::<Result<Vec<Condition>, TemplateError>>()?;
+
+            return Ok(Condition::Or(conditions));
+        }
+
+        let requirements = Self::split_outside_quotes(content, " and ");
+
+        if requirements.len() > 1 {
+            let conditions = requirements.into_iter()
+                .map(|requirement| self.parse_condition(requirement, line))
+                .collect::<Result<Vec<Condition>, TemplateError>>()?;
+
+            return Ok(Condition::And(conditions));
+        }
+
+        let content = content.trim();
+
+        if let Some(negated) = content.strip_prefix("not ") {
+            return Ok(Condition::Not(Box::new(self.parse_condition(negated, line)?)));
+        }
+
+        for operator in ["==", "!="] {
+            if let [left, right] = Self::split_outside_quotes(content, operator).as_slice() {
+                let left = self.parse_expression(left, line)?;
+                let right = self.parse_expression(right, line)?;
+
+                return Ok(if operator == "==" { Condition::Equals(left, right) } else { Condition::NotEquals(left, right) });
+            }
+        }
+
+        Ok(Condition::Value(self.parse_expression(content, line)?))
+    }
+
+    fn parse_expression(&self, content: &str, line: usize) -> Result<Expression, TemplateError> {
+        let content = content.trim();
+
+        for quote in ['"', '\''] {
+            if content.len() >= 2 && content.starts_with(quote) && content.ends_with(quote) {
+                return Ok(Expression::Literal(Value::String(content[1..content.len() - 1].to_string())));
+            }
+        }
+
+        match content {
+            "true" => return Ok(Expression::Literal(Value::Bool(true))),
+            "false" => return Ok(Expression::Literal(Value::Bool(false))),
+            "null" | "none" => return Ok(Expression::Literal(Value::Null)),
+            _ => ()
+        }
+
+        if content.starts_with(|ch: char| ch.is_ascii_digit() || ch == '-') {
+            return match serde_json::from_str::<Value>(content) {
+                Ok(number @ Value::Number(_)) => Ok(Expression::Literal(number)),
+                _ => Err(self.error(line, format!("Invalid number: {:?}", content)))
+            };
+        }
+
+        let path = content.split('.').map(|part| part.to_string()).collect::<Vec<String>>();
+
+        if path.iter().any(|part| !Self::is_identifier(part) && part.parse::<usize>().is_err()) {
+            return Err(self.error(line, format!("Invalid expression: {:?}", content)));
+        }
+
+        Ok(Expression::Path(path))
+    }
+
+    fn split_outside_quotes<'b>(content: &'b str, separator: &str) -> Vec<&'b str> {
+        let mut parts = Vec::new();
+        let mut quote = None;
+        let mut start = 0;
+        let mut index = 0;
+
+        while index < content.len() {
+            let ch = content[index..].chars().next().unwrap_or_default();
+
+            match quote {
+                Some(open) if ch == open => quote = None,
+                Some(_) => (),
+                None if ch == '"' || ch == '\'' => quote = Some(ch),
+                None if content[index..].starts_with(separator) => {
+                    parts.push(&content[start..index]);
+                    index += separator.len();
+                    start = index;
+                    continue;
+                },
+                None => ()
+            }
+
+            index += ch.len_utf8();
+        }
+
+        parts.push(&content[start..]);
+        parts
+    }
+
+    fn is_identifier(name: &str) -> bool {
+        name.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_')
+            && name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+    }
+
+    fn error(&self, line: usize, message: String) -> TemplateError {
+        TemplateError::Syntax(self.name.to_string(), line, message)
+    }
+}
+
+struct Renderer<'a> {
+    source: &'a dyn TemplateSource,
+    scopes: Vec<Map<String, Value>>,
+    blocks: HashMap<String, &'a [Node]>,
+    depth: usize
+}
+
+impl Renderer<'_> {
+    fn render_nodes(&mut self, template: &Template, nodes: &[Node], output: &mut String) -> Result<(), TemplateError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => output.push_str(text),
+                Node::Output(expression, filters) => {
+                    let value = self.evaluate(expression);
+                    output.push_str(&Self::apply_filters(value, filters));
+                },
+                Node::If(branches, otherwise) => {
+                    let branch = branches.iter()
+                        .find(|(condition, _)| self.test(condition))
+                        .map(|(_, nodes)| nodes)
+                        .unwrap_or(otherwise);
+
+                    self.render_nodes(template, branch, output)?;
+                },
+                Node::For(key, value, iterable, body, otherwise) => {
+                    let items = match self.evaluate(iterable) {
+                        Value::Array(items) => items.into_iter().map(|item| (Value::Null, item)).collect(),
+                        Value::Object(map) => map.into_iter().map(|(key, item)| (Value::String(key), item)).collect(),
+                        _ => Vec::new()
+                    };
+
+                    if items.is_empty() {
+                        self.render_nodes(template, otherwise, output)?;
+                        continue;
+                    }
+
+                    let length = items.len();
+
+                    for (index, (item_key, item)) in items.into_iter().enumerate() {
+                        let mut scope = Map::new();
+
+                        if let Some(key) = key {
+                            scope.insert(key.clone(), if item_key.is_null() { Value::from(index) } else { item_key });
+                        }
+
+                        scope.insert(value.clone(), item);
+                        scope.insert(String::from("loop"), serde_json::json!({
+                            "index": index + 1,
+                            "index0": index,
+                            "first": index == 0,
+                            "last": index + 1 == length,
+                            "length": length
+                        }));
+
+                        self.scopes.push(scope);
+                        let result = self.render_nodes(template, body, output);
+                        self.scopes.pop();
+
+                        result?;
+                    }
+                },
+                Node::Include(name) => {
+                    if self.depth >= MAX_DEPTH {
+                        return Err(TemplateError::Render(template.name.clone(), format!("Too many nested includes of {}", name)));
+                    }
+
+                    let included = self.source.get_template(name)?;
+
+                    self.depth += 1;
+                    let result = self.render_nodes(&included, &included.nodes, output);
+                    self.depth -= 1;
+
+                    result?;
+                },
+                Node::Block(name) => {
+                    let nodes = self.blocks.get(name).copied()
+                        .or_else(|| template.blocks.get(name).map(|nodes| nodes.as_slice()))
+                        .unwrap_or_default();
+
+                    self.render_nodes(template, nodes, output)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn evaluate(&self, expression: &Expression) -> Value {
+        match expression {
+            Expression::Literal(value) => value.clone(),
+            Expression::Path(path) => {
+                let Some((first, rest)) = path.split_first() else {
+                    return Value::Null;
+                };
+
+                let Some(mut value) = self.scopes.iter().rev().find_map(|scope| scope.get(first)) else {
+                    return Value::Null;
+                };
+
+                for part in rest {
+                    let next = match value {
+                        Value::Object(map) => map.get(part),
+                        Value::Array(items) => part.parse::<usize>().ok().and_then(|index| items.get(index)),
+                        _ => None
+                    };
+
+                    match next {
+                        Some(next) => value = next,
+                        None => return Value::Null
+                    }
+                }
+
+                value.clone()
+            }
+        }
+    }
+
+    fn test(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Value(expression) => Self::is_truthy(&self.evaluate(expression)),
+            Condition::Not(condition) => !self.test(condition),
+            Condition::Equals(left, right) => self.evaluate(left) == self.evaluate(right),
+            Condition::NotEquals(left, right) => self.evaluate(left) != self.evaluate(right),
+            Condition::And(conditions) => conditions.iter().all(|condition| self.test(condition)),
+            Condition::Or(conditions) => conditions.iter().any(|condition| self.test(condition))
+        }
+    }
+
+    fn is_truthy(value: &Value) -> bool {
+        match value {
+            Value::Null => false,
+            Value::Bool(value) => *value,
+            Value::Number(number) => number.as_f64().is_some_and(|number| number != 0.0),
+            Value::String(value) => !value.is_empty(),
+            Value::Array(items) => !items.is_empty(),
+            Value::Object(map) => !map.is_empty()
+        }
+    }
+
+    fn apply_filters(value: Value, filters: &[Filter]) -> String {
+        let mut value = value;
+
+        for filter in filters {
+            value = match (filter, value) {
+                (Filter::Upper, value) => Value::String(Self::to_text(&value).to_uppercase()),
+                (Filter::Lower, value) => Value::String(Self::to_text(&value).to_lowercase()),
+                (Filter::Length, Value::Array(items)) => Value::from(items.len()),
+                (Filter::Length, Value::Object(map)) => Value::from(map.len()),
+                (Filter::Length, Value::String(text)) => Value::from(text.chars().count()),
+                (Filter::Length, _) => Value::from(0),
+                (Filter::Raw, value) => value
+            };
+        }
+
+        let text = Self::to_text(&value);
+
+        if filters.contains(&Filter::Raw) {
+            text
+        } else {
+            Self::escape(&text)
+        }
+    }
+
+    fn to_text(value: &Value) -> String {
+        match value {
+            Value::Null => String::new(),
+            Value::String(value) => value.clone(),
+            value => value.to_string()
+        }
+    }
+
+    fn escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+
+        for ch in text.chars() {
+            match ch {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#39;"),
+                ch => escaped.push(ch)
+            }
+        }
+
+        escaped
+    }
+}
@@ -15,27 +15,47 @@ impl UrlEncoding {
         encoded
     }
 
+    /// Decode a percent-encoded path, as UTF-8 when it is valid, otherwise one char per byte
     pub fn url_decode(input: String) -> Result<String, String> {
-        let mut decoded = String::new();
-        let mut chars = input.chars();
-
-        while let Some(ch) = chars.next() {
-            if ch == '%' {
-                let hex: String = chars.by_ref().take(2).collect();
-                if hex.len() == 2 {
-                    if let Ok(byte) = u8::from_str_radix(&hex, 16) {
-                        decoded.push(byte as char);
-                    } else {
-                        return Err(String::from("Invalid percent-encoded sequence"));
-                    }
-                } else {
-                    return Err(String::from("Incomplete percent-encoded sequence"));
-                }
-            } else {
-                decoded.push(ch);
+        let decoded = Self::percent_decode(&input)?;
+
+        Ok(match String::from_utf8(decoded) {
+            Ok(decoded) => decoded,
+            Err(err) => err.into_bytes().into_iter().map(char::from).collect()
+        })
+    }
+
+    /// Decode percent-encoded UTF-8, as sent by forms, refusing sequences that are not valid UTF-8
+    pub fn url_decode_utf8(input: String) -> Result<String, String> {
+        let decoded = Self::percent_decode(&input)?;
+
+        String::from_utf8(decoded).map_err(|_| String::from("Invalid UTF-8 in percent-encoded sequence"))
+    }
+
+    /// Decode the percent-encoded bytes, the chars that are not encoded keep their UTF-8 bytes.
+    /// Multibyte chars span several encoded bytes, so the bytes are only read as text by the caller.
+    pub fn percent_decode(input: &str) -> Result<Vec<u8>, String> {
+        let bytes = input.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut index = 0;
+
+        while let Some(&byte) = bytes.get(index) {
+            if byte != b'%' {
+                decoded.push(byte);
+                index += 1;
+                continue;
             }
+
+            let hex = bytes.get(index + 1..index + 3).ok_or_else(|| String::from("Incomplete percent-encoded sequence"))?;
+
+            let value = hex.iter()
+                .try_fold(0, |value, &digit| Some(value * 16 + char::from(digit).to_digit(16)? as u8))
+                .ok_or_else(|| String::from("Invalid percent-encoded sequence"))?;
+
+            decoded.push(value);
+            index += 3;
         }
 
         Ok(decoded)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,175 @@
+use std::fmt::{Display, Formatter};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use crate::query::QueryParamValue::{Multiple, Single};
+use crate::query::{QueryParam, QueryParamValueType};
+use crate::query::QueryParamValueType::{Boolean, Float, Int, Str, UInt};
+
+lazy_static! {
+    static ref EMAIL_REGEX: Regex = Regex::new(r"^[^@\s]+@[^@\s.]+(\.[^@\s.]+)+$").unwrap();
+    static ref UUID_REGEX: Regex = Regex::new(r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$").unwrap();
+}
+
+/// A constraint on the value of a query, path or body field, declared after its type in the route DSL:
+/// `/people?age:uint|min=18|max=120&name|len=2..50&role|in=admin,user&email?|email`,
+/// or with `#[validate(min = 18, len = "2..50", email)]` on the fields of an `InjectStruct`.
+/// A `regex=` rule takes the rest of the spec, so it can contain `|`.
+/// On text, `min` and `max` bound its length like `len=3..` and `len=..50` do.
+#[derive(Debug, Clone)]
+pub enum Rule {
+    Min(f64),
+    Max(f64),
+    Length(Option<usize>, Option<usize>),
+    Regex(Regex),
+    OneOf(Vec<String>),
+    Email,
+    Uuid
+}
+
+impl Rule {
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let (name, value) = match rule.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => (rule.trim(), "")
+        };
+
+        let number = |value: &str| value.parse::<f64>().map_err(|_| format!("Invalid number in rule: {}", rule));
+        let length = |value: &str| match value {
+            "" => Ok(None),
+            value => value.parse::<usize>().map(Some).map_err(|_| format!("Invalid length in rule: {}", rule))
+        };
+
+        match name {
+            "min" => Ok(Rule::Min(number(value)?)),
+            "max" => Ok(Rule::Max(number(value)?)),
+            // `len=5`, `len=2..50`, `len=2..` or `len=..50`
+            "len" => match value.split_once("..") {
+                Some((min, max)) => Ok(Rule::Length(length(min)?, length(max)?)),
+                None => Ok(Rule::Length(length(value)?, length(value)?))
+            },
+            "regex" => Regex::new(value).map(Rule::Regex).map_err(|err| format!("Invalid regex in rule {}: {}", rule, err)),
+            "in" => Ok(Rule::OneOf(value.split(',').map(|value| value.trim().to_string()).collect())),
+            "email" => Ok(Rule::Email),
+            "uuid" => Ok(Rule::Uuid),
+            _ => Err(format!("Unknown validation rule: {}", rule))
+        }
+    }
+
+    /// Parse `|` separated rules, the spec of a field without its name and type
+    pub fn parse_all(rules: &str) -> Result<Vec<Self>, String> {
+        let mut result = Vec::new();
+        let mut rest = rules;
+
+        while !rest.is_empty() {
+            let rule = if rest.trim_start().starts_with("regex=") { rest } else { rest.split('|').next().unwrap_or_default() };
+
+            result.push(Self::parse(rule)?);
+            rest = rest[rule.len()..].strip_prefix('|').unwrap_or_default();
+        }
+
+        Ok(result)
+    }
+
+    /// Check a value, returning the message of the violation, e.g. `age must be at least 18`
+    pub fn check(&self, name: &str, value: &QueryParamValueType) -> Result<(), String> {
+        match (self, value) {
+            (Rule::Min(min), Str(_)) => return Rule::Length(Some(min.ceil() as usize), None).check(name, value),
+            (Rule::Max(max), Str(_)) => return Rule::Length(None, Some(max.floor() as usize)).check(name, value),
+            _ => ()
+        }
+
+        let number = match value {
+            Int(value) => Some(*value as f64),
+            UInt(value) => Some(*value as f64),
+            Float(value) => Some(*value),
+            Str(_) | Boolean(_) => None
+        };
+        let text = match value {
+            Str(value) => value.clone(),
+            Int(value) => value.to_string(),
+            UInt(value) => value.to_string(),
+            Float(value) => value.to_string(),
+            Boolean(value) => value.to_string()
+        };
+
+        let is_valid = match self {
+            Rule::Min(min) => number.is_none_or(|number| number >= *min),
+            Rule::Max(max) => number.is_none_or(|number| number <= *max),
+            Rule::Length(min, max) => {
+                let length = text.chars().count();
+                min.is_none_or(|min| length >= min) && max.is_none_or(|max| length <= max)
+            },
+            Rule::Regex(regex) => regex.is_match(&text),
+            Rule::OneOf(values) => values.contains(&text),
+            Rule::Email => EMAIL_REGEX.is_match(&text),
+            Rule::Uuid => UUID_REGEX.is_match(&text)
+        };
+
+        if is_valid {
+            return Ok(());
+        }
+
+        Err(format!("{} {}", name, self))
+    }
+
+    /// Check every value of a param, arrays are valid when all of their items are
+    pub fn check_param(rules: &[Rule], name: &str, param: &QueryParam) -> Result<(), String> {
+        let values = match &param.value {
+            Single(value) => Vec::from([value]),
+            Multiple(values) => values.iter().collect()
+        };
+
+        for value in values {
+            for rule in rules {
+                rule.check(name, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the value of a field, as it would be serialized to JSON
+    pub fn check_value<V: Serialize + ?Sized>(rules: &[Rule], name: &str, value: &V) -> Result<(), String> {
+        Self::check_json(rules, name, &serde_json::to_value(value).unwrap_or_default())
+    }
+
+    /// Check a field of a JSON body, `null` stands for an absent optional field
+    pub fn check_json(rules: &[Rule], name: &str, value: &Value) -> Result<(), String> {
+        let value = match value {
+            Value::Null => return Ok(()),
+            Value::Array(values) => {
+                return values.iter().try_for_each(|value| Self::check_json(rules, name, value));
+            },
+            Value::Bool(value) => Boolean(*value),
+            Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+                (Some(value), _) => UInt(value as usize),
+                (None, Some(value)) => Int(value as isize),
+                _ => Float(number.as_f64().unwrap_or_default())
+            },
+            Value::String(value) => Str(value.clone()),
+            Value::Object(_) => Str(value.to_string())
+        };
+
+        rules.iter().try_for_each(|rule| rule.check(name, &value))
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::Min(min) => write!(f, "must be at least {}", min),
+            Rule::Max(max) => write!(f, "must be at most {}", max),
+            Rule::Length(Some(min), Some(max)) if min == max => write!(f, "must be {} characters long", min),
+            Rule::Length(Some(min), Some(max)) => write!(f, "must be between {} and {} characters long", min, max),
+            Rule::Length(Some(min), None) => write!(f, "must be at least {} characters long", min),
+            Rule::Length(None, Some(max)) => write!(f, "must be at most {} characters long", max),
+            Rule::Length(None, None) => write!(f, "has an invalid length"),
+            Rule::Regex(regex) => write!(f, "must match {}", regex.as_str()),
+            Rule::OneOf(values) => write!(f, "must be one of {}", values.join(", ")),
+            Rule::Email => write!(f, "must be an email address"),
+            Rule::Uuid => write!(f, "must be a UUID")
+        }
+    }
+}
@@ -1,4 +1,6 @@
+use crate::error::ErrorsHashMap;
 use crate::request::{RequestQueriesHashMap};
+use crate::validation::Rule;
 
 pub trait InjectStructTrait: 'static {
     fn init() -> Self
@@ -8,4 +10,17 @@ pub trait InjectStructTrait: 'static {
     fn from_hashmap(hashmap: &RequestQueriesHashMap) -> Self
         where
             Self: Sized;
-}
\ No newline at end of file
+
+    /// The `#[validate(...)]` rules of the fields, by field name
+    fn rules() -> &'static [(&'static str, Vec<Rule>)]
+        where
+            Self: Sized {
+        &[]
+    }
+
+    /// Check the values of the fields against their `#[validate(...)]` rules,
+    /// the errors are named like the fields are in a deserialized body, `#[serde(rename)]` included
+    fn check_rules(&self) -> ErrorsHashMap {
+        ErrorsHashMap::new()
+    }
+}
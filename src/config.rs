@@ -0,0 +1,168 @@
+use std::cmp::max;
+use std::env;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use shared::form_data::DEFAULT_UPLOAD_DIR;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1";
+pub const DEFAULT_PORTS: Range<u16> = 8080..8091;
+pub const DEFAULT_THREADS: usize = 4;
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_MAX_REQUESTS: usize = 100;
+pub const DEFAULT_MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+pub const DEFAULT_VIEWS_DIR: &str = "src/views";
+pub const DEFAULT_PUBLIC_DIR: &str = "src/public";
+
+/// Where and how `WRust` listens.
+/// Without an explicit port, the first port of `port_range` free on every address is used,
+/// and port `0` lets the OS pick an ephemeral port, reported back by `WRust::local_addrs`.
+/// Relative views, public and upload directories are resolved when the server starts:
+/// next to the executable when they exist there, so an installed server finds its files wherever it is started from,
+/// and from the current directory otherwise.
+#[derive(Debug, Clone)]
+pub struct WRustConfig {
+    pub addresses: Vec<String>,
+    pub port: Option<u16>,
+    pub port_range: Range<u16>,
+    pub threads: usize,
+    pub shutdown_timeout: Duration,
+    pub keep_alive_timeout: Duration,
+    pub max_requests_per_connection: usize,
+    pub max_body_size: usize,
+    pub hot_reload: bool,
+    pub views_dir: PathBuf,
+    pub public_dir: PathBuf,
+    pub upload_dir: PathBuf
+}
+
+impl Default for WRustConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WRustConfig {
+    pub fn new() -> Self {
+        WRustConfig {
+            addresses: Vec::new(),
+            port: None,
+            port_range: DEFAULT_PORTS,
+            threads: DEFAULT_THREADS,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            keep_alive_timeout: DEFAULT_KEEP_ALIVE_TIMEOUT,
+            max_requests_per_connection: DEFAULT_MAX_REQUESTS,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            hot_reload: cfg!(debug_assertions),
+            views_dir: PathBuf::from(DEFAULT_VIEWS_DIR),
+            public_dir: PathBuf::from(DEFAULT_PUBLIC_DIR),
+            upload_dir: env::temp_dir().join(DEFAULT_UPLOAD_DIR)
+        }
+    }
+
+    /// Add an address to listen on: IPv4, IPv6 (`::1` or `[::1]`) or a host name like `localhost`
+    pub fn bind(mut self, address: &str) -> Self {
+        self.addresses.push(address.to_string());
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn port_range(mut self, port_range: Range<u16>) -> Self {
+        self.port_range = port_range;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// How long in-flight requests are given to finish once a shutdown is requested
+    pub fn shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    /// How long an idle persistent connection is kept open waiting for its next request
+    pub fn keep_alive_timeout(mut self, keep_alive_timeout: Duration) -> Self {
+        self.keep_alive_timeout = keep_alive_timeout;
+        self
+    }
+
+    /// How many requests a persistent connection serves before being closed, `1` disables keep-alive
+    pub fn max_requests_per_connection(mut self, max_requests: usize) -> Self {
+        self.max_requests_per_connection = max(max_requests, 1);
+        self
+    }
+
+    /// The largest request body accepted, in bytes, larger ones are answered with `413 Payload Too Large`
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Reload the views changed on disk without restarting, enabled by default in debug builds
+    pub fn hot_reload(mut self, hot_reload: bool) -> Self {
+        self.hot_reload = hot_reload;
+        self
+    }
+
+    /// Where `Response::view` and `Response::render` look up views
+    pub fn views_dir<P: AsRef<Path>>(mut self, views_dir: P) -> Self {
+        self.views_dir = views_dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Where the static files served by `WRust::serve_public` live
+    pub fn public_dir<P: AsRef<Path>>(mut self, public_dir: P) -> Self {
+        self.public_dir = public_dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Where the files of `multipart/form-data` requests are saved, the temporary directory by default
+    pub fn upload_dir<P: AsRef<Path>>(mut self, upload_dir: P) -> Self {
+        self.upload_dir = upload_dir.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn get_views_dir(&self) -> PathBuf {
+        Self::resolve_dir(&self.views_dir)
+    }
+
+    pub fn get_public_dir(&self) -> PathBuf {
+        Self::resolve_dir(&self.public_dir)
+    }
+
+    pub fn get_upload_dir(&self) -> PathBuf {
+        Self::resolve_dir(&self.upload_dir)
+    }
+
+    // Relative directories are looked up next to the executable, then in the current directory
+    fn resolve_dir(dir: &Path) -> PathBuf {
+        if dir.is_absolute() {
+            return dir.to_path_buf();
+        }
+
+        let next_to_executable = env::current_exe().ok()
+            .and_then(|executable| executable.parent().map(|parent| parent.join(dir)))
+            .filter(|path| path.is_dir());
+
+        match next_to_executable {
+            Some(path) => path,
+            None => env::current_dir().unwrap_or_default().join(dir)
+        }
+    }
+
+    pub fn get_addresses(&self) -> Vec<String> {
+        if self.addresses.is_empty() {
+            return Vec::from([String::from(DEFAULT_ADDRESS)]);
+        }
+
+        self.addresses.clone()
+    }
+}
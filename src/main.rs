@@ -1,32 +1,73 @@
+#[cfg(test)]
 mod test;
 mod thread_pool;
-mod wrust;
+mod shutdown;
+pub mod wrust;
+pub mod config;
 pub mod router;
+pub mod route_tree;
 pub mod person;
-mod route_builder;
+pub mod route_builder;
+pub mod static_files;
 
 extern crate lazy_static;
 
+use std::env;
 use std::process::exit;
 use std::sync::Arc;
-use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use shared::cookie::{Cookie, SameSite};
+use shared::middleware::from_fn;
 use shared::query::QueryParamValue::Single;
 use shared::query::QueryParamValueType::{Str, UInt};
-use shared::request::RequestData::{Json};
-use crate::person::{DATA, Person};
+use crate::config::{DEFAULT_ADDRESS, WRustConfig};
+use crate::person::{CreatePerson, DATA, Person};
+use crate::static_files::StaticFiles;
 use crate::wrust::WRust;
 
 fn main(){
-    let mut app = WRust::new();
+    let mut config = WRustConfig::new()
+        .bind(&env::var("WRUST_ADDRESS").unwrap_or(String::from(DEFAULT_ADDRESS)));
+
+    if let Ok(port) = env::var("WRUST_PORT") {
+        match port.parse::<u16>() {
+            Ok(port) => config = config.port(port),
+            Err(_) => {
+                eprintln!("Invalid WRUST_PORT: {:?}", port);
+                exit(1);
+            }
+        }
+    }
+
+    let mut app = WRust::with_config(config);
+    match env::var("WRUST_SECRET") {
+        Ok(secret) => app.secret(&secret),
+        Err(_) => {
+            eprintln!("WRUST_SECRET is not set, cookies are signed with a random key and will not survive a restart");
+            app.random_secret()
+        }
+    };
+    app.middleware(from_fn(|request, response, next| {
+        let started = Instant::now();
+        let (method, path) = (request.method.as_str().to_string(), request.path.clone());
+
+        let response = next.run(request, response);
+        println!("{} {} {} {:?}", method, path, response.get_status(), started.elapsed());
+
+        response
+    }));
+
+    let public_dir = app.get_config().get_public_dir();
+    app.mount_static("/public", StaticFiles::new(public_dir).cache_control("public, max-age=3600"));
 
     {
         let binding = Arc::clone(&app.router);
         let mut router = binding.write().unwrap();
 
-        router.get(String::from("/get?name?&age:uint"), Box::new(move | _request, response| {
+        router.route("/get?name?&age:uint").get(move | _request, response| {
             let age = if let Some(param) = _request.queries_map.get("age") {
                 match &param.value {
-                    Single(UInt(age)) => age.clone(),
+                    Single(UInt(age)) => *age,
                     _ => 0
                 }
             } else {
@@ -48,76 +89,109 @@ fn main(){
             }).collect::<Vec<&Person>>();
 
             response.json(data)
-        }));
+        });
+
+        router.group("/people", |people| {
+            people.route("/:id:uint")
+                .name("person")
+                .tags(&["people"])
+                .get(move | _request, response| {
+                    let id = match _request.params.get("id") {
+                        Some(param) => match &param.value {
+                            Single(UInt(id)) => *id,
+                            _ => 0
+                        },
+                        None => 0
+                    };
 
-        router.get(String::from("/get-view"), Box::new(move | _request, response| {
-            response.view("")
-        }));
+                    let binding = DATA.clone().read().unwrap().clone();
 
-        router.get(String::from("/get-nested-view"), Box::new(move | _request, response| {
-            response.view("nested")
-        }));
-
-        router.get(String::from("/get-nested-view-test"), Box::new(move | _request, response| {
-            println!("{:?}", _request.data);
-            response.view("nested/test")
-        }));
-
-        router.post(String::from("/create"), Box::new(move | _request, response| {
-            match _request.data {
-                Json(data) => {
-                    let age = if let Some(age_value) = data.get("age") {
-                        if let Some(age) = age_value.as_u64(){
-                            if age < 18 || age > 120 {
-                                response.status(400);
-                                return response.text(format!("Invalid Age: {}", age));
-                            }
-
-                            age as usize
-                        } else {
-                            response.status(400);
-                            return response.text(String::from("Age Not Provided"));
+                    match binding.iter().find(|person| person.id == id) {
+                        Some(person) => response.json(person),
+                        None => {
+                            response.status(404);
+                            response.text(format!("Person Not Found: {}", id))
                         }
-                    } else {
-                        response.status(400);
-                        return response.text(String::from("Age Not Provided"));
+                    }
+                })
+                .delete(move | _request, response| {
+                    let id = match _request.params.get("id") {
+                        Some(param) => match &param.value {
+                            Single(UInt(id)) => *id,
+                            _ => 0
+                        },
+                        None => 0
                     };
 
-                    let name = if let Some(name_value) = data.get("name") {
-                        if let Some(name) = name_value.as_str(){
-                            if name.len() < 2 {
-                                response.status(400);
-                                return response.text(format!("Invalid Age: {}", age));
-                            }
-
-                            name.to_string()
-                        } else {
-                            response.status(400);
-                            return response.text(String::from("Name Not Provided"));
+                    let mut data = DATA.write().unwrap();
+
+                    match data.iter().position(|person| person.id == id) {
+                        Some(index) => response.json(data.remove(index)),
+                        None => {
+                            response.status(404);
+                            response.text(format!("Person Not Found: {}", id))
                         }
-                    } else {
-                        response.status(400);
-                        return response.text(String::from("Name Not Provided"));
-                    };
+                    }
+                });
+        });
+
+        router.route("/get-view").get(move | _request, response| {
+            response.view("")
+        });
 
-                    let person = Person::new(age, name);
-                    DATA.clone().write().unwrap().push(person.clone());
-                    response.json(person)
-                },
-                _ => {
-                    response.status(400);
-                    response.text(String::from("Bad Request"))
+        router.route("/get-nested-view").get(move | _request, response| {
+            response.view("nested")
+        });
+
+        router.route("/get-nested-view-test").get(move | _request, response| {
+            let people = DATA.read().unwrap().clone();
+
+            response.render("nested/test", &serde_json::json!({ "people": people }))
+        });
+
+        router.route("/create").post(move | _request, response| {
+            let data = match _request.validated_json::<CreatePerson>() {
+                Ok(data) => data,
+                Err(err) => {
+                    response.status(err.get_status());
+                    return response.json(err);
                 }
+            };
+
+            let person = Person::new(data.age, data.name);
+            DATA.clone().write().unwrap().push(person.clone());
+            response.json(person)
+        });
+
+        router.route("/visits").get(move | _request, response| {
+            let visits = _request.cookies.get_signed("visits")
+                .and_then(|cookie| cookie.value.parse::<usize>().ok())
+                .unwrap_or(0) + 1;
+
+            let cookie = Cookie::new(String::from("visits"), visits.to_string())
+                .path("/")
+                .http_only(true)
+                .same_site(SameSite::Lax);
+
+            if let Err(err) = response.cookies().add_signed(cookie) {
+                response.status(500);
+                return response.text(err);
             }
-        }));
 
-        router.all(String::from("/all"), Box::new(move | _request, response| {
+            response.text(format!("Visits: {}", visits))
+        });
+
+        router.route("/all").all(move | _request, response| {
             response.text(String::from("Hello from any endpoint"))
-        }));
+        });
+    }
+
+    if let Err(err) = app.shutdown_on_signals() {
+        eprintln!("{}", err);
     }
 
     if let Err(err) = app.listen() {
-        eprintln!("Failed to bind to a port: {:?}", err);
+        eprintln!("Failed to start the server: {}", err);
         exit(1);
     }
 }
\ No newline at end of file
@@ -31,11 +31,19 @@ impl Person {
 
         *current_id += 1;
 
-        let id = current_id.clone();
+        let id = *current_id;
         Self {
             id,
             age,
             name
         }
     }
-}
\ No newline at end of file
+}
+// The body of `POST /create`
+#[derive(Deserialize, InjectStruct, Debug)]
+pub struct CreatePerson {
+    #[validate(len = "2..")]
+    pub name: String,
+    #[validate(min = 18, max = 120)]
+    pub age: usize
+}
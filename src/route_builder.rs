@@ -1,24 +1,156 @@
-use shared::route::{Handler, Route, RouteMethod};
+use std::sync::Arc;
+use shared::middleware::Middleware;
+use shared::query::QueriesHashMap;
+use shared::request::{HttpMethod, Request};
+use shared::response::Response;
+use shared::route::{Handler, Route, RouteMetadata, RouteMethod};
+use shared::route::RouteMethod::{RouteAny, RouteCustom, RouteDelete, RouteGet, RouteHead, RouteOptions, RoutePatch, RoutePost, RoutePut};
 use crate::router::Router;
 
-pub struct RouteBuilder {
-    router: &'static Router,
-    handler: Box<Handler>,
+/// Registers the handlers of one path, with the middleware and metadata they share.
+/// The routes are added to the router by `build`, or when the builder is dropped:
+///
+/// `router.route("/people").get(list).post(create).name("people").tags(&["people"]);`
+pub struct RouteBuilder<'a> {
+    router: &'a mut Router,
     path: String,
-    method: RouteMethod
+    handlers: Vec<(RouteMethod, Box<Handler>)>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    body: Result<QueriesHashMap, String>,
+    metadata: RouteMetadata
 }
 
-impl RouteBuilder {
-    pub fn new(router: &'static mut Router, method: RouteMethod, mut path: String, handler: Box<Handler>) -> Self {
+impl<'a> RouteBuilder<'a> {
+    pub fn new(router: &'a mut Router, path: &str) -> Self {
+        let mut path = path.to_string();
+
         if !path.starts_with('/') {
             path.insert(0, '/');
         }
 
         RouteBuilder {
             router,
-            handler,
             path,
-            method
+            handlers: Vec::new(),
+            middlewares: Vec::new(),
+            body: Ok(QueriesHashMap::new()),
+            metadata: RouteMetadata::default()
         }
     }
-}
\ No newline at end of file
+
+    pub fn get<F>(self, handler: F) -> Self
+    where F: Fn(Request, &mut Response) -> &Response + Send + Sync + 'static {
+        self.handler(RouteGet, Box::new(handler))
+    }
+
+    pub fn post<F>(self, handler: F) -> Self
+    where F: Fn(Request, &mut Response) -> &Response + Send + Sync + 'static {
+        self.handler(RoutePost, Box::new(handler))
+    }
+
+    pub fn put<F>(self, handler: F) -> Self
+    where F: Fn(Request, &mut Response) -> &Response + Send + Sync + 'static {
+        self.handler(RoutePut, Box::new(handler))
+    }
+
+    pub fn patch<F>(self, handler: F) -> Self
+    where F: Fn(Request, &mut Response) -> &Response + Send + Sync + 'static {
+        self.handler(RoutePatch, Box::new(handler))
+    }
+
+    pub fn delete<F>(self, handler: F) -> Self
+    where F: Fn(Request, &mut Response) -> &Response + Send + Sync + 'static {
+        self.handler(RouteDelete, Box::new(handler))
+    }
+
+    pub fn head<F>(self, handler: F) -> Self
+    where F: Fn(Request, &mut Response) -> &Response + Send + Sync + 'static {
+        self.handler(RouteHead, Box::new(handler))
+    }
+
+    pub fn options<F>(self, handler: F) -> Self
+    where F: Fn(Request, &mut Response) -> &Response + Send + Sync + 'static {
+        self.handler(RouteOptions, Box::new(handler))
+    }
+
+    /// Handle an extension method, e.g. `PROPFIND` or `PURGE`
+    pub fn method<F>(self, method: &str, handler: F) -> Self
+    where F: Fn(Request, &mut Response) -> &Response + Send + Sync + 'static {
+        let method = match HttpMethod::from(&method.to_uppercase()) {
+            Some(method) => method.get_route_method(),
+            None => RouteCustom(method.to_string())
+        };
+
+        self.handler(method, Box::new(handler))
+    }
+
+    pub fn all<F>(self, handler: F) -> Self
+    where F: Fn(Request, &mut Response) -> &Response + Send + Sync + 'static {
+        self.handler(RouteAny, Box::new(handler))
+    }
+
+    pub fn handler(mut self, method: RouteMethod, handler: Box<Handler>) -> Self {
+        self.handlers.push((method, handler));
+        self
+    }
+
+    /// Run a middleware around every handler of the builder, after the middleware of the router
+    pub fn middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// The fields expected in a form body, typed and validated like the queries of the path:
+    /// `.body("name&age:uint&tags+")`
+    pub fn body(mut self, specs: &str) -> Self {
+        self.body = Route::generate_params(specs);
+        self
+    }
+
+    /// A unique name to find the route with `Router::get_route_by_name`
+    pub fn name(mut self, name: &str) -> Self {
+        self.metadata.name = Some(name.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.metadata.description = Some(description.to_string());
+        self
+    }
+
+    pub fn tags(mut self, tags: &[&str]) -> Self {
+        self.metadata.tags.extend(tags.iter().map(|tag| tag.to_string()));
+        self
+    }
+
+    /// Register the handlers now rather than when the builder is dropped
+    pub fn build(mut self) {
+        self.register();
+    }
+
+    fn register(&mut self) {
+        for (method, handler) in self.handlers.drain(..) {
+            let result = Route::new(self.path.clone(), handler)
+                .and_then(|(route, _)| self.body.clone().map(|body| (route, body)));
+
+            match result {
+                Ok((mut route, body)) => {
+                    route.middlewares = self.middlewares.clone();
+                    route.body = body;
+                    route.metadata = self.metadata.clone();
+
+                    self.router.insert_route(method, route);
+                },
+                Err(err) => {
+                    self.router.reject_route(&self.path, err);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for RouteBuilder<'_> {
+    fn drop(&mut self) {
+        self.register();
+    }
+}
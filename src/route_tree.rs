@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::mem;
+use shared::query::{QueryParam, QueryParamType};
+use shared::query::QueryParamValueType::Str;
+use shared::request::RequestQueriesHashMap;
+use shared::route::{PathSegment, Route, RouteMethod};
+use shared::route::RouteMethod::{RouteAny, RouteGet, RouteHead, RouteOptions};
+
+// A node holds the routes registered on its exact path, one per method
+#[derive(Default)]
+pub struct RouteNode {
+    pub routes: HashMap<RouteMethod, Route>,
+    // Keyed by the first segment of each edge, so no two edges share a prefix
+    statics: HashMap<String, StaticEdge>,
+    params: Vec<ParamNode>,
+    wildcards: Vec<WildcardNode>
+}
+
+impl RouteNode {
+    /// The route answering the method, HEAD requests fall back to the GET route
+    pub fn route_for(&self, method: &RouteMethod) -> Option<&Route> {
+        self.routes.get(method)
+            .or_else(|| if *method == RouteHead { self.routes.get(&RouteGet) } else { None })
+            .or_else(|| self.routes.get(&RouteAny))
+    }
+
+    /// Every method this path answers to, including the implicit HEAD and OPTIONS ones
+    pub fn allowed_methods(&self) -> Vec<RouteMethod> {
+        let mut methods = Vec::new();
+
+        for method in self.routes.keys() {
+            match method {
+                RouteAny => methods.extend(RouteMethod::STANDARD),
+                RouteGet => methods.extend([RouteGet, RouteHead]),
+                method => methods.push(method.clone())
+            }
+        }
+
+        methods.push(RouteOptions);
+
+        methods.sort_by_key(|method| {
+            let position = RouteMethod::STANDARD.iter().position(|standard| standard == method);
+            (position.unwrap_or(RouteMethod::STANDARD.len()), method.as_str().to_string())
+        });
+        methods.dedup();
+
+        methods
+    }
+}
+
+// A chain of static segments without branches is a single edge, holding the segments after its key
+struct StaticEdge {
+    rest: Vec<String>,
+    node: RouteNode
+}
+
+impl StaticEdge {
+    // Whether the parts start with the rest of the edge
+    fn matches(&self, parts: &[&str]) -> bool {
+        parts.len() >= self.rest.len() && self.rest.iter().zip(parts).all(|(segment, part)| segment == part)
+    }
+
+    // Keep the first `at` segments of the rest, the other ones become a child edge of a new node
+    fn split(&mut self, at: usize) {
+        let mut tail = self.rest.split_off(at).into_iter();
+
+        if let Some(key) = tail.next() {
+            let child = StaticEdge { rest: tail.collect(), node: mem::take(&mut self.node) };
+            self.node.statics.insert(key, child);
+        }
+    }
+}
+
+// Dynamic children are kept in registration order, which is also their matching order.
+// Captures with another name get their own child, so every route receives its values under its own names.
+struct ParamNode {
+    name: String,
+    param_type: QueryParamType,
+    node: RouteNode
+}
+
+struct WildcardNode {
+    name: String,
+    node: Box<RouteNode>
+}
+
+/// Radix tree over path segments used by the `Router` to match request paths.
+/// Static segments without branches share one edge, which is split when a route diverges from it.
+/// Lookups prefer static edges over params, and params over wildcards,
+/// and backtrack when a branch does not lead to a match.
+#[derive(Default)]
+pub struct RouteTree {
+    root: RouteNode,
+    size: usize
+}
+
+impl RouteTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Insert the route under the given method, replacing any route with the same pattern and method
+    pub fn insert(&mut self, method: RouteMethod, route: Route) {
+        let segments = route.segments.clone();
+        let mut node = &mut self.root;
+        let mut index = 0;
+
+        while let Some(segment) = segments.get(index) {
+            index += 1;
+
+            node = match segment {
+                PathSegment::Static(value) => {
+                    // The static segments following this one, all of them go on the edge of a new branch
+                    let run = segments[index..].iter()
+                        .map_while(|segment| match segment {
+                            PathSegment::Static(value) => Some(value.clone()),
+                            _ => None
+                        })
+                        .collect::<Vec<String>>();
+
+                    let edge = node.statics.entry(value.clone()).or_insert_with(|| StaticEdge {
+                        rest: run.clone(),
+                        node: RouteNode::default()
+                    });
+
+                    let common = edge.rest.iter().zip(&run).take_while(|(segment, value)| segment == value).count();
+
+                    if common < edge.rest.len() {
+                        edge.split(common);
+                    }
+
+                    index += common;
+                    &mut edge.node
+                },
+                PathSegment::Param(name, param_type) => {
+                    let position = node.params.iter().position(|param| {
+                        param.name == *name && param.param_type._type == param_type._type
+                    });
+
+                    let position = match position {
+                        Some(position) => position,
+                        None => {
+                            node.params.push(ParamNode { name: name.clone(), param_type: param_type.clone(), node: RouteNode::default() });
+                            node.params.len() - 1
+                        }
+                    };
+
+                    &mut node.params[position].node
+                },
+                PathSegment::Wildcard(name) => {
+                    let position = match node.wildcards.iter().position(|wildcard| wildcard.name == *name) {
+                        Some(position) => position,
+                        None => {
+                            node.wildcards.push(WildcardNode { name: name.clone(), node: Box::default() });
+                            node.wildcards.len() - 1
+                        }
+                    };
+
+                    &mut node.wildcards[position].node
+                }
+            };
+        }
+
+        if node.routes.insert(method, route).is_none() {
+            self.size += 1;
+        }
+    }
+
+    pub fn routes(&self) -> Vec<(&RouteMethod, &Route)> {
+        let mut routes = Vec::new();
+        let mut nodes = Vec::from([&self.root]);
+
+        while let Some(node) = nodes.pop() {
+            routes.extend(node.routes.iter());
+            nodes.extend(node.statics.values().map(|edge| &edge.node));
+            nodes.extend(node.params.iter().map(|param| &param.node));
+            nodes.extend(node.wildcards.iter().map(|wildcard| wildcard.node.as_ref()));
+        }
+
+        routes
+    }
+
+    /// Take every route out of the tree, with the method it was registered under
+    pub fn into_routes(self) -> Vec<(RouteMethod, Route)> {
+        let mut routes = Vec::new();
+        Self::collect_routes(self.root, &mut routes);
+
+        routes
+    }
+
+    fn collect_routes(node: RouteNode, routes: &mut Vec<(RouteMethod, Route)>) {
+        routes.extend(node.routes);
+
+        for (_, edge) in node.statics {
+            Self::collect_routes(edge.node, routes);
+        }
+
+        for param in node.params {
+            Self::collect_routes(param.node, routes);
+        }
+
+        for wildcard in node.wildcards {
+            Self::collect_routes(*wildcard.node, routes);
+        }
+    }
+
+    /// Find the most specific node matching the path and accepted by the predicate,
+    /// with the params captured along the way.
+    /// When no route matches, params also match values of the wrong type, without capturing them,
+    /// so the route refuses them when validating its path instead of the path not being found.
+    pub fn find<F>(&self, path: &str, accept: F) -> Option<(&RouteNode, RequestQueriesHashMap)>
+        where F: Fn(&RouteNode) -> bool
+    {
+        let parts = path.split('/').filter(|part| !part.is_empty()).collect::<Vec<&str>>();
+        let mut params = RequestQueriesHashMap::new();
+
+        let node = Self::find_node(&self.root, &parts, &accept, false, &mut params)
+            .or_else(|| Self::find_node(&self.root, &parts, &accept, true, &mut params))?;
+        Some((node, params))
+    }
+
+    fn find_node<'a, F>(node: &'a RouteNode, parts: &[&str], accept: &F, lenient: bool, params: &mut RequestQueriesHashMap) -> Option<&'a RouteNode>
+        where F: Fn(&RouteNode) -> bool
+    {
+        let Some((part, rest)) = parts.split_first() else {
+            if accept(node) {
+                return Some(node);
+            }
+
+            // An empty tail is still a match for a wildcard
+            return Self::find_wildcard(node, parts, accept, params);
+        };
+
+        if let Some(edge) = node.statics.get(*part).filter(|edge| edge.matches(rest)) {
+            if let Some(found) = Self::find_node(&edge.node, &rest[edge.rest.len()..], accept, lenient, params) {
+                return Some(found);
+            }
+        }
+
+        for param in &node.params {
+            let value = QueryParam::from(part.to_string(), param.param_type._type.clone(), false);
+
+            if value.is_none() && !lenient {
+                continue;
+            }
+
+            if let Some(found) = Self::find_node(&param.node, rest, accept, lenient, params) {
+                if let Some(value) = value {
+                    params.insert(param.name.clone(), value);
+                }
+
+                return Some(found);
+            }
+        }
+
+        Self::find_wildcard(node, parts, accept, params)
+    }
+
+    fn find_wildcard<'a, F>(node: &'a RouteNode, parts: &[&str], accept: &F, params: &mut RequestQueriesHashMap) -> Option<&'a RouteNode>
+        where F: Fn(&RouteNode) -> bool
+    {
+        let wildcard = node.wildcards.iter().find(|wildcard| accept(&wildcard.node))?;
+
+        let value = QueryParam::from(parts.join("/"), Str(String::new()), false)?;
+        params.insert(wildcard.name.clone(), value);
+
+        Some(&wildcard.node)
+    }
+}
@@ -1,36 +1,157 @@
-use std::collections::HashMap;
-use std::fmt::{Debug};
-use shared::request::{HttpMethod, Request};
-use shared::response::Response;
-use shared::route::{Handler, MethodsHashMap, Route, RouteMethod};
-use shared::route::RouteMethod::{RouteAny, RouteGet, RoutePost};
+use std::path::Path;
+use std::sync::Arc;
+use shared::middleware::Middleware;
+use shared::request::{HttpMethod, RequestQueriesHashMap};
+use shared::route::{Handler, Route, RouteMethod};
+use shared::route::RouteMethod::{RouteAny, RouteCustom, RouteDelete, RouteGet, RouteHead, RouteOptions, RoutePatch, RoutePost, RoutePut};
+use crate::route_builder::RouteBuilder;
+use crate::route_tree::RouteTree;
+use crate::static_files::{STATIC_PATH_PARAM, StaticFiles};
+
+// What a request resolves to: a registered route, or the implicit OPTIONS answer listing the allowed methods
+pub enum Endpoint<'a> {
+    Route(&'a Route, RequestQueriesHashMap),
+    Options(Vec<RouteMethod>)
+}
+
+// Why a request has no endpoint: nothing lives at the path, or something does but under other methods
+#[derive(Debug)]
+pub enum RouteError {
+    NotFound(String),
+    MethodNotAllowed(Vec<RouteMethod>)
+}
 
 pub struct Router {
-    routes: MethodsHashMap,
+    routes: RouteTree,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    // Routes that could not be built, `WRust::listen` refuses to start with them
+    errors: Vec<String>,
     listening: bool
 }
 
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Router {
     pub fn new() -> Router {
         Router{
-            routes: HashMap::new(),
+            routes: RouteTree::new(),
+            middlewares: Vec::new(),
+            errors: Vec::new(),
             listening: false
         }
     }
 
     pub fn get(&mut self, path: String, handler: Box<Handler>) -> &Self {
-        self.add_route(RouteGet, path, handler)
+        self.add_route(RouteGet, path, handler, Vec::new())
     }
 
     pub fn post(&mut self, path: String, handler: Box<Handler>) -> &Self {
-        self.add_route(RoutePost, path, handler)
+        self.add_route(RoutePost, path, handler, Vec::new())
+    }
+
+    pub fn put(&mut self, path: String, handler: Box<Handler>) -> &Self {
+        self.add_route(RoutePut, path, handler, Vec::new())
+    }
+
+    pub fn patch(&mut self, path: String, handler: Box<Handler>) -> &Self {
+        self.add_route(RoutePatch, path, handler, Vec::new())
+    }
+
+    pub fn delete(&mut self, path: String, handler: Box<Handler>) -> &Self {
+        self.add_route(RouteDelete, path, handler, Vec::new())
+    }
+
+    pub fn head(&mut self, path: String, handler: Box<Handler>) -> &Self {
+        self.add_route(RouteHead, path, handler, Vec::new())
+    }
+
+    pub fn options(&mut self, path: String, handler: Box<Handler>) -> &Self {
+        self.add_route(RouteOptions, path, handler, Vec::new())
+    }
+
+    /// Register an extension method, e.g. `PROPFIND` or `PURGE`
+    pub fn method(&mut self, method: &str, path: String, handler: Box<Handler>) -> &Self {
+        let method = match HttpMethod::from(&method.to_uppercase()) {
+            Some(method) => method.get_route_method(),
+            None => RouteCustom(method.to_string())
+        };
+
+        self.add_route(method, path, handler, Vec::new())
     }
 
     pub fn all(&mut self, path: String, handler: Box<Handler>) -> &Self {
-        self.add_route(RouteAny, path, handler)
+        self.add_route(RouteAny, path, handler, Vec::new())
+    }
+
+    /// Serve the files of a directory under a path prefix, e.g. `serve_static("/public", "src/public")`
+    pub fn serve_static<P: AsRef<Path>>(&mut self, prefix: &str, dir: P) -> &Self {
+        self.mount_static(prefix, StaticFiles::new(dir))
+    }
+
+    pub fn mount_static(&mut self, prefix: &str, static_files: StaticFiles) -> &Self {
+        let path = format!("{}/*{}", prefix.trim_end_matches('/'), STATIC_PATH_PARAM);
+
+        self.get(path, Box::new(move |request, response| static_files.handle(request, response)))
+    }
+
+    /// Run a middleware around the handlers of every route of this router, in registration order
+    pub fn middleware(&mut self, middleware: Arc<dyn Middleware>) -> &Self {
+        if !self.listening {
+            self.middlewares.push(middleware);
+        }
+
+        self
+    }
+
+    pub fn get_middlewares(&self) -> &[Arc<dyn Middleware>] {
+        &self.middlewares
+    }
+
+    /// Register routes under a shared prefix, e.g. `group("/api/v1", |api| { api.get(...); })`.
+    /// Middleware added to the group only runs for the routes of the group, and groups can be nested.
+    pub fn group<F>(&mut self, prefix: &str, build: F) -> &Self
+        where F: FnOnce(&mut Router)
+    {
+        let mut group = Router::new();
+        build(&mut group);
+
+        self.mount(prefix, group)
     }
 
-    fn add_route(&mut self, method: RouteMethod, mut path: String, handler: Box<Handler>) -> &Self {
+    /// Add the routes of a router built separately under a prefix, its middleware runs after the one of this router
+    pub fn mount(&mut self, prefix: &str, router: Router) -> &Self {
+        if self.listening {
+            return self;
+        }
+
+        let prefix = prefix.trim_matches('/');
+        let segments = match Route::generate_segments(prefix) {
+            Ok(segments) => segments,
+            Err(err) => {
+                self.reject_route(prefix, err);
+                return self;
+            }
+        };
+
+        self.errors.extend(router.errors);
+
+        for (method, mut route) in router.routes.into_routes() {
+            route.path = if prefix.is_empty() { route.path } else { format!("/{}{}", prefix, route.path) };
+            route.segments = segments.iter().cloned().chain(route.segments).collect();
+            route.middlewares = router.middlewares.iter().cloned().chain(route.middlewares).collect();
+
+            self.routes.insert(method, route);
+        }
+
+        self
+    }
+
+    /// Register a route with middleware of its own, run after the middleware of the router
+    pub fn add_route(&mut self, method: RouteMethod, mut path: String, handler: Box<Handler>, middlewares: Vec<Arc<dyn Middleware>>) -> &Self {
         if self.listening {
             return self;
         }
@@ -39,38 +160,90 @@ impl Router {
             path.insert(0, '/');
         }
 
-        let (route, path) = Route::new(path.clone(), handler);
+        match Route::new(path.clone(), handler) {
+            Ok((mut route, _)) => {
+                route.middlewares = middlewares;
+                self.insert_route(method, route)
+            },
+            Err(err) => self.reject_route(&path, err)
+        }
+    }
+
+    /// Start a route registered with a fluent builder,
+    /// e.g. `route("/people").get(|request, response| ...).name("people")`
+    pub fn route(&mut self, path: &str) -> RouteBuilder<'_> {
+        RouteBuilder::new(self, path)
+    }
+
+    /// Register an already built route, replacing any route with the same pattern and method
+    pub fn insert_route(&mut self, method: RouteMethod, route: Route) -> &Self {
+        if !self.listening {
+            self.routes.insert(method, route);
+        }
+
+        self
+    }
+
+    /// Record a route that could not be built, instead of registering it
+    pub fn reject_route(&mut self, path: &str, err: String) -> &Self {
+        let err = format!("{}: {}", path, err);
 
-        if !self.routes.contains_key(&method) {
-            self.routes.insert(method, HashMap::new());
+        // Every handler of a builder is rejected for the same reason
+        if !self.listening && !self.errors.contains(&err) {
+            self.errors.push(err);
         }
 
-        self.routes.get_mut(&method).unwrap().insert(path, route);
         self
     }
 
+    /// Why some routes could not be built, e.g. an unknown validation rule or an invalid regex
+    pub fn get_errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// Every registered route with its method, in no particular order
+    pub fn get_routes(&self) -> Vec<(&RouteMethod, &Route)> {
+        self.routes.routes()
+    }
+
+    pub fn get_route_by_name(&self, name: &str) -> Option<&Route> {
+        self.routes.routes().into_iter()
+            .map(|(_, route)| route)
+            .find(|route| route.metadata.name.as_deref() == Some(name))
+    }
+
+    pub fn routes_count(&self) -> usize {
+        self.routes.len()
+    }
+
     pub fn start_listening(&mut self){
         self.listening = true;
     }
 
-    pub fn get_request_endpoint(&self, method: HttpMethod, path: &String) -> Result<&Route, String>{
-        if let Some(handler) = self.get_method_endpoint(&method.get_route_method(), path) {
-            return Ok(handler);
+    pub fn get_request_endpoint(&self, method: &HttpMethod, path: &str) -> Result<Endpoint<'_>, RouteError>{
+        let route_method = method.get_route_method();
+
+        // `OPTIONS *` asks about the server as a whole
+        if route_method == RouteOptions && path == "*" {
+            return Ok(Endpoint::Options(RouteMethod::STANDARD.to_vec()));
         }
 
-        if let Some(handler) = self.get_method_endpoint(&RouteAny, path) {
-            return Ok(handler);
+        if let Some((node, params)) = self.routes.find(path, |node| node.route_for(&route_method).is_some()) {
+            if let Some(route) = node.route_for(&route_method) {
+                return Ok(Endpoint::Route(route, params));
+            }
         }
 
-        Err(format!("No corresponding endpoint: {:?}", path))
-    }
+        if let Some((node, _)) = self.routes.find(path, |node| !node.routes.is_empty()) {
+            let methods = node.allowed_methods();
 
-    fn get_method_endpoint(&self, method: &RouteMethod, path: &String) -> Option<&Route> {
-        if let Some(routes) = self.routes.get(method) {
-            let route = routes.get(path);
-            return route;
+            if route_method == RouteOptions {
+                return Ok(Endpoint::Options(methods));
+            }
+
+            return Err(RouteError::MethodNotAllowed(methods));
         }
 
-        None
+        Err(RouteError::NotFound(format!("No corresponding endpoint: {:?}", path)))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,101 @@
+use std::path::{Component, Path, PathBuf};
+use shared::constants::CACHE_CONTROL_HEADER;
+use shared::query::QueryParamValue::Single;
+use shared::query::QueryParamValueType::Str;
+use shared::request::Request;
+use shared::response::Response;
+
+pub const DEFAULT_INDEX_FILE: &str = "index.html";
+
+// Name of the wildcard param holding the requested file, relative to the mount
+pub const STATIC_PATH_PARAM: &str = "path";
+
+/// A directory served under a path prefix, see `Router::serve_static`.
+/// Files are looked up relative to `root`, and directories are answered with their index file.
+/// Every file is sent with an `ETag` and a `Last-Modified` header, and with `cache_control` when set.
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    pub root: PathBuf,
+    pub index_files: Vec<String>,
+    pub cache_control: Option<String>
+}
+
+impl StaticFiles {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        StaticFiles {
+            root: root.as_ref().to_path_buf(),
+            index_files: Vec::from([String::from(DEFAULT_INDEX_FILE)]),
+            cache_control: None
+        }
+    }
+
+    /// Replace the file names tried, in order, when a directory is requested
+    pub fn index_files(mut self, index_files: &[&str]) -> Self {
+        self.index_files = index_files.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// The `Cache-Control` header sent with the files, e.g. `public, max-age=3600`
+    pub fn cache_control(mut self, cache_control: &str) -> Self {
+        self.cache_control = Some(cache_control.to_string());
+        self
+    }
+
+    pub fn handle<'a>(&self, request: Request, response: &'a mut Response) -> &'a Response {
+        let path = match request.params.get(STATIC_PATH_PARAM) {
+            Some(param) => match &param.value {
+                Single(Str(path)) => path.clone(),
+                _ => String::new()
+            },
+            None => String::new()
+        };
+
+        match self.resolve(&path) {
+            Ok(file) => {
+                response.file(file);
+
+                if let (Some(cache_control), 200) = (&self.cache_control, response.get_status()) {
+                    response.set_header(String::from(CACHE_CONTROL_HEADER), cache_control.clone());
+                }
+
+                response
+            },
+            Err(status) => {
+                response.status(status);
+                response.text(String::from(if status == 403 { "Access Denied" } else { "Not Found" }))
+            }
+        }
+    }
+
+    /// The file answering the relative path, or the status to respond with
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, usize> {
+        let mut file = self.root.clone();
+
+        // Only plain names are allowed, `..` or absolute parts would leave the root
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => file.push(name),
+                Component::CurDir => (),
+                _ => return Err(403)
+            }
+        }
+
+        if file.is_dir() {
+            file = self.index_files.iter()
+                .map(|name| file.join(name))
+                .find(|index| index.is_file())
+                .ok_or(403_usize)?;
+        }
+
+        let (Ok(root), Ok(file)) = (self.root.canonicalize(), file.canonicalize()) else {
+            return Err(404);
+        };
+
+        // Symbolic links may still point outside of the root
+        if !file.starts_with(&root) {
+            return Err(403);
+        }
+
+        Ok(file)
+    }
+}
@@ -1,14 +1,115 @@
-use shared::query::QueryParamValueType::Str;
-use shared::route::Route;
+use std::{env, fs, process};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use shared::cookie::{Cookie, CookieJar, CookieKey, SameSite};
+use shared::error::{ServerError, TemplateError};
+use shared::http_date::HttpDate;
+use shared::query::QueryParam;
+use shared::query::QueryParamValue::{Multiple, Single};
+use shared::query::QueryParamValueType::{Str, UInt};
+use shared::request::{HttpMethod, RequestQueriesHashMap};
+use shared::request::RequestData::{Form, Json, Text};
+use shared::request::HttpMethod::{DELETE, GET, HEAD, OPTIONS, POST};
+use shared::response::Response;
+use shared::middleware::from_fn;
+use shared::route::{Route, RouteMethod};
+use shared::template::{TemplateRegistry, TemplateSource, ViewsDirectory};
+use shared::url_encoding::UrlEncoding;
+use crate::config::WRustConfig;
+use crate::router::{Endpoint, RouteError, Router};
+use crate::shutdown::ShutdownHandle;
+use crate::static_files::StaticFiles;
+use crate::wrust::WRust;
 
-#[cfg(test)]
+fn endpoint_params(router: &Router, method: &HttpMethod, path: &str) -> Option<RequestQueriesHashMap> {
+    match router.get_request_endpoint(method, path) {
+        Ok(Endpoint::Route(_, params)) => Some(params),
+        _ => None
+    }
+}
+
+// A server listening on an ephemeral port, until it is stopped
+struct TestServer {
+    handle: ShutdownHandle,
+    server: JoinHandle<Result<(), ServerError>>
+}
+
+impl TestServer {
+    // Shut the server down, waiting for its in-flight requests and the end of `listen`
+    fn stop(self) -> Result<(), ServerError> {
+        self.handle.shutdown();
+        self.server.join().unwrap()
+    }
+}
+
+fn spawn_test_server(mut app: WRust) -> (SocketAddr, TestServer) {
+    let address = app.bind().unwrap()[0];
+    let handle = app.shutdown_handle();
+    let server = thread::spawn(move || app.listen());
+
+    (address, TestServer { handle, server })
+}
+
+// Send the bytes of one or more requests, and read the responses until the connection is closed
+fn send_raw<B: AsRef<[u8]>>(address: SocketAddr, request: B) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(request.as_ref()).unwrap();
+
+    let mut output = String::new();
+    stream.read_to_string(&mut output).unwrap();
+    output
+}
+
+// A folder of the temporary directory, removed with its content when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        Self::new_in(&env::temp_dir(), name)
+    }
+
+    fn new_in(parent: &Path, name: &str) -> Self {
+        let path = parent.join(format!("wrust-{}-{}", name, process::id()));
+        fs::create_dir_all(&path).unwrap();
+
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
 pub fn route_query_parser_should_match_result(){
     // Arrange
     let path = "/hello?age:int&name?&amount:float&is_subscribed:bool&address:string?*".to_string();
     let keys = ["age", "name", "amount", "is_subscribed", "address"];
 
     // Act
-    let (result, _) = Route::generate_queries(path);
+    let (result, _) = Route::generate_queries(path).unwrap();
 
     // Assert
     for key in keys {
@@ -24,3 +125,1333 @@ pub fn route_query_parser_should_match_result(){
     assert!(address.flags.is_array);
     assert!(address.flags.allow_empty);
 }
+
+#[test]
+pub fn route_path_params_should_capture_segments(){
+    // Arrange
+    let (_, path) = Route::new("/people/:id:uint/files/*rest?sort?".to_string(), Box::new(|_, response| response)).unwrap();
+
+    let mut router = Router::new();
+    router.get(String::from("/people/:id:uint/files/*rest?sort?"), Box::new(|_, response| response));
+    router.post(String::from("/people/:id:uint/files/*path"), Box::new(|_, response| response));
+    router.get(String::from("/teams/:id:uint"), Box::new(|_, response| response));
+    router.get(String::from("/teams/*rest"), Box::new(|_, response| response));
+
+    // Act
+    let get = endpoint_params(&router, &GET, "/people/42/files/docs/cv.pdf").unwrap();
+    let post = endpoint_params(&router, &POST, "/people/42/files/docs/cv.pdf").unwrap();
+    let mistyped = endpoint_params(&router, &GET, "/people/abc/files/cv.pdf").unwrap();
+    let team = endpoint_params(&router, &GET, "/teams/abc").unwrap();
+
+    // Assert
+    assert_eq!(path, "/people/:id:uint/files/*rest");
+    assert!(matches!(get.get("id").unwrap().value, Single(UInt(42))));
+    assert!(matches!(&get.get("rest").unwrap().value, Single(Str(rest)) if rest == "docs/cv.pdf"));
+    assert!(matches!(&post.get("path").unwrap().value, Single(Str(path)) if path == "docs/cv.pdf"));
+    assert!(!post.contains_key("rest"));
+    // A value of the wrong type still reaches the route, which refuses it when validating the path
+    assert!(!mistyped.contains_key("id"));
+    assert!(matches!(&team.get("rest").unwrap().value, Single(Str(rest)) if rest == "abc"));
+    assert!(endpoint_params(&router, &GET, "/people/42").is_none());
+    assert!(Route::new(String::from("/people/:id?"), Box::new(|_, response| response)).is_err());
+    assert!(Route::new(String::from("/people/:id:uint?/files"), Box::new(|_, response| response)).is_err());
+    assert!(Route::new(String::from("/people/:id?sort"), Box::new(|_, response| response)).is_ok());
+}
+
+#[test]
+pub fn router_should_match_thousands_of_routes_by_precedence(){
+    // Arrange
+    let app = WRust::with_config(WRustConfig::new().port(0));
+    let count = 2000;
+    {
+        let mut router = app.router.write().unwrap();
+
+        for i in 0..count {
+            router.get(format!("/resource{i}/:id"), Box::new(|request, response| {
+                match request.params.get("id") {
+                    Some(QueryParam { value: Single(Str(id)) }) => response.text(format!("param {}", id)),
+                    _ => response.status(400)
+                }
+            }));
+            router.get(format!("/resource{i}/latest"), Box::new(|_, response| response.text(String::from("static"))));
+            router.get(format!("/resource{i}/*rest"), Box::new(|request, response| {
+                match request.params.get("rest") {
+                    Some(QueryParam { value: Single(Str(rest)) }) => response.text(format!("wildcard {}", rest)),
+                    _ => response.status(400)
+                }
+            }));
+        }
+    }
+
+    // Act
+    {
+        let router = app.router.read().unwrap();
+
+        for i in 0..count {
+            let params = endpoint_params(&router, &GET, &format!("/resource{i}/{i}")).unwrap();
+            assert!(matches!(&params.get("id").unwrap().value, Single(Str(id)) if *id == i.to_string()));
+
+            // `latest` is also a valid `:id`, the static segment has to win
+            let params = endpoint_params(&router, &GET, &format!("/resource{i}/latest")).unwrap();
+            assert!(params.is_empty());
+
+            let params = endpoint_params(&router, &GET, &format!("/resource{i}/a/b")).unwrap();
+            assert!(matches!(&params.get("rest").unwrap().value, Single(Str(rest)) if rest == "a/b"));
+        }
+
+        assert_eq!(router.routes_count(), count * 3);
+        assert!(endpoint_params(&router, &GET, &format!("/resource{count}/1")).is_none());
+        assert!(endpoint_params(&router, &POST, "/resource1/1").is_none());
+    }
+
+    let (address, server) = spawn_test_server(app);
+    let request = |path: String| send_raw(address, format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path));
+
+    let outputs = [0, count / 2, count - 1].map(|i| (
+        request(format!("/resource{i}/{i}")),
+        request(format!("/resource{i}/latest")),
+        request(format!("/resource{i}/a/b"))
+    ));
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+
+    for (i, (param, latest, wildcard)) in [0, count / 2, count - 1].into_iter().zip(outputs) {
+        assert!(param.ends_with(&format!("\r\n\r\nparam {}", i)));
+        assert!(latest.ends_with("\r\n\r\nstatic"));
+        assert!(wildcard.ends_with("\r\n\r\nwildcard a/b"));
+    }
+}
+
+#[test]
+pub fn router_should_split_shared_static_prefixes(){
+    // Arrange
+    let mut router = Router::new();
+    router.get(String::from("/api/v1/people/list"), Box::new(|_, response| response));
+    router.get(String::from("/api/v1"), Box::new(|_, response| response));
+    router.get(String::from("/api/v1/people/:id"), Box::new(|_, response| response));
+    router.get(String::from("/api/v2/people/*rest"), Box::new(|_, response| response));
+    router.get(String::from("/api"), Box::new(|_, response| response));
+
+    // Act
+    let list = endpoint_params(&router, &GET, "/api/v1/people/list");
+    let version = endpoint_params(&router, &GET, "/api/v1");
+    let person = endpoint_params(&router, &GET, "/api/v1/people/42");
+    let rest = endpoint_params(&router, &GET, "/api/v2/people/a/b");
+    let api = endpoint_params(&router, &GET, "/api");
+
+    // Assert
+    assert_eq!(router.routes_count(), 5);
+    assert!(list.is_some_and(|params| params.is_empty()));
+    assert!(version.is_some_and(|params| params.is_empty()));
+    assert!(matches!(&person.unwrap().get("id").unwrap().value, Single(Str(id)) if id == "42"));
+    assert!(matches!(&rest.unwrap().get("rest").unwrap().value, Single(Str(rest)) if rest == "a/b"));
+    assert!(api.is_some_and(|params| params.is_empty()));
+    assert!(endpoint_params(&router, &GET, "/api/v1/people").is_none());
+    assert!(endpoint_params(&router, &GET, "/api/v2").is_none());
+    assert!(endpoint_params(&router, &GET, "/api/v1/list").is_none());
+}
+
+#[test]
+pub fn router_should_answer_head_and_options_implicitly(){
+    // Arrange
+    let mut router = Router::new();
+    router.get(String::from("/people/:id"), Box::new(|_, response| response));
+    router.delete(String::from("/people/:id"), Box::new(|_, response| response));
+    router.method("purge", String::from("/people/:id"), Box::new(|_, response| response));
+
+    // Act
+    let head = endpoint_params(&router, &HEAD, "/people/1");
+    let options = router.get_request_endpoint(&OPTIONS, "/people/1");
+    let custom = endpoint_params(&router, &HttpMethod::from("PURGE").unwrap(), "/people/1");
+
+    // Assert
+    assert!(head.is_some());
+    assert!(custom.is_some());
+    assert!(matches!(router.get_request_endpoint(&DELETE, "/people"), Err(RouteError::NotFound(_))));
+    assert!(matches!(router.get_request_endpoint(&POST, "/people/1"), Err(RouteError::MethodNotAllowed(methods)) if methods.len() == 5));
+
+    let Ok(Endpoint::Options(methods)) = options else { panic!("Expected an implicit OPTIONS answer") };
+    let methods = methods.iter().map(|method| method.as_str()).collect::<Vec<&str>>();
+    assert_eq!(methods, ["GET", "DELETE", "HEAD", "OPTIONS", "PURGE"]);
+}
+
+#[test]
+pub fn response_should_write_headers_and_cookies(){
+    // Arrange
+    let mut response = Response::new();
+    let mut cookie = Cookie::new(String::from("session"), String::from("abc"));
+    cookie.path = Some(String::from("/"));
+    cookie.expires = Some(UNIX_EPOCH + Duration::from_secs(784111777));
+    cookie.max_age = Some(3600);
+    cookie.http_only = true;
+    cookie.same_site = Some(SameSite::Lax);
+
+    response.set_header(String::from("X-Request-Id"), String::from("42\r\nInjected: yes"));
+    response.add_cookie(cookie);
+    response.status(201);
+    response.text(String::from("created"));
+
+    // Act
+    let mut output = Vec::new();
+    response.write_to(&mut output, false).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    // Assert
+    assert!(output.starts_with("HTTP/1.1 201 Created\r\nContent-Length: 7\r\nContent-Type: text/plain\r\n"));
+    assert!(output.contains("X-Request-Id: 42Injected: yes\r\n"));
+    assert!(output.contains("Set-Cookie: session=abc; Path=/; Expires=Sun, 06 Nov 1994 08:49:37 GMT; Max-Age=3600; HttpOnly; SameSite=Lax\r\n"));
+    assert!(output.ends_with("\r\n\r\ncreated"));
+}
+
+#[test]
+pub fn cookie_jar_should_reject_tampered_signed_and_private_cookies(){
+    // Arrange
+    let key = Arc::new(CookieKey::from_secret("secret"));
+    let mut response_jar = CookieJar::new();
+    response_jar.set_key(Arc::clone(&key));
+
+    response_jar.add_signed(Cookie::new(String::from("user"), String::from("42"))).unwrap();
+    response_jar.add_private(Cookie::new(String::from("session"), String::from("s3cr3t"))).unwrap();
+
+    let header = response_jar.iter().map(|cookie| format!("{}={}", cookie.name, cookie.value)).collect::<Vec<String>>().join("; ");
+
+    // Act
+    let mut request_jar = CookieJar::new();
+    request_jar.set_key(Arc::clone(&key));
+    request_jar.add_from_header(&header);
+
+    let mut tampered_jar = request_jar.clone();
+    let signed = tampered_jar.get_value("user").unwrap().replacen("42", "43", 1);
+    tampered_jar.add(Cookie::new(String::from("user"), signed));
+    let private = tampered_jar.get_value("session").unwrap().clone();
+    tampered_jar.add(Cookie::new(String::from("other"), private));
+
+    // Assert
+    assert_ne!(request_jar.get_value("session").unwrap(), "s3cr3t");
+    assert_eq!(request_jar.get_signed("user").unwrap().value, "42");
+    assert_eq!(request_jar.get_private("session").unwrap().value, "s3cr3t");
+    assert!(tampered_jar.get_signed("user").is_none());
+    assert!(tampered_jar.get_private("other").is_none());
+    assert!(CookieJar::new().add_signed(Cookie::new(String::from("user"), String::from("42"))).is_err());
+    assert!(CookieKey::generate().verify("user", &CookieKey::generate().sign("user", "42")).is_none());
+    // The signing key is derived with HKDF-SHA256
+    assert_eq!(key.sign("user", "42"), "42.ScdVHxTZGUw93zLw3qMKLrimm8tJBRDOuSrYdWxiPIA");
+    // Names are matched like header names
+    assert_eq!(request_jar.get_signed("USER").unwrap().value, "42");
+    assert_eq!(request_jar.get_private("Session").unwrap().value, "s3cr3t");
+}
+
+#[test]
+pub fn wrust_should_bind_an_ephemeral_port_and_report_it(){
+    // Arrange
+    let mut app = WRust::with_config(WRustConfig::new().bind("127.0.0.1").port(0));
+
+    // Act
+    let addresses = app.bind().unwrap();
+    let port = addresses[0].port();
+    let mut taken = WRust::with_config(WRustConfig::new().bind("127.0.0.1").port(port));
+    let mut invalid = WRust::with_config(WRustConfig::new().bind("not an address").port(0));
+    let mut exhausted = WRust::with_config(WRustConfig::new().bind("127.0.0.1").port_range(port..port + 1));
+    let exhausted = exhausted.bind().unwrap_err();
+    let listen = ServerError::Listen(addresses[0], io::Error::other("refused"));
+
+    // Assert
+    assert_eq!(addresses.len(), 1);
+    assert_ne!(port, 0);
+    assert_eq!(app.local_addrs(), addresses);
+    assert!(matches!(taken.bind(), Err(ServerError::AddressInUse(address)) if address.port() == port));
+    assert!(matches!(invalid.bind(), Err(ServerError::InvalidAddress(_))));
+    assert_eq!(exhausted.to_string(), format!("No port is available in this range [{}, {})", port, port + 1));
+    assert_eq!(listen.to_string(), format!("Failed to accept connections on {}: refused", addresses[0]));
+}
+
+#[test]
+pub fn wrust_should_shut_down_gracefully(){
+    // Arrange
+    let app = WRust::with_config(WRustConfig::new().port(0).shutdown_timeout(Duration::from_secs(5)));
+    app.router.write().unwrap().get(String::from("/slow"), Box::new(|_, response| {
+        thread::sleep(Duration::from_millis(200));
+        response.text(String::from("done"))
+    }));
+
+    let (address, server) = spawn_test_server(app);
+
+    // Act
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let stopped = server.stop();
+
+    let mut output = String::new();
+    stream.read_to_string(&mut output).unwrap();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(output.starts_with("HTTP/1.1 200 OK"));
+    assert!(output.ends_with("done"));
+    assert!(TcpStream::connect(address).is_err());
+}
+
+#[test]
+pub fn wrust_should_serve_pipelined_requests_on_a_kept_alive_connection(){
+    // Arrange
+    let app = WRust::with_config(WRustConfig::new().port(0).max_requests_per_connection(3));
+    app.router.write().unwrap().post(String::from("/echo"), Box::new(|request, response| {
+        response.text(format!("{} {}", request.method.as_str(), request.path))
+    }));
+
+    let (address, server) = spawn_test_server(app);
+
+    // Act
+    let output = send_raw(address, concat!(
+        "POST /echo HTTP/1.1\r\nContent-Length: 5\r\n\r\nfirst",
+        "POST /echo HTTP/1.1\r\nContent-Length: 6\r\n\r\nsecond",
+        "POST /echo HTTP/1.1\r\n\r\n",
+        "POST /echo HTTP/1.1\r\n\r\n"
+    ));
+    let closed_output = send_raw(address, "POST /echo HTTP/1.1\r\nConnection: close\r\n\r\n");
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 3);
+    assert_eq!(output.matches("Connection: keep-alive\r\n").count(), 2);
+    assert_eq!(output.matches("Connection: close\r\n").count(), 1);
+    assert!(output.ends_with("POST /echo"));
+    assert!(closed_output.contains("Connection: close\r\n"));
+}
+
+#[test]
+pub fn wrust_should_serve_new_clients_while_connections_are_idle(){
+    // Arrange
+    let app = WRust::with_config(WRustConfig::new().port(0).threads(2).keep_alive_timeout(Duration::from_secs(10)));
+    app.router.write().unwrap().get(String::from("/"), Box::new(|_, response| {
+        response.text(String::from("ok"))
+    }));
+
+    let (address, server) = spawn_test_server(app);
+
+    // Act
+    let mut idle = Vec::new();
+
+    for index in 0..4 {
+        let mut stream = TcpStream::connect(address).unwrap();
+
+        // Half of the connections never send a request, the others stay open after their first response
+        if index % 2 == 1 {
+            stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+
+            let mut output = Vec::new();
+            let mut buffer = [0; 256];
+
+            while !output.ends_with(b"ok") {
+                let read = stream.read(&mut buffer).unwrap();
+                assert_ne!(read, 0);
+                output.extend_from_slice(&buffer[..read]);
+            }
+        }
+
+        idle.push(stream);
+    }
+
+    let started = Instant::now();
+    let output = send_raw(address, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let elapsed = started.elapsed();
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(output.starts_with("HTTP/1.1 200 OK"));
+    assert!(elapsed < Duration::from_secs(2));
+}
+
+#[test]
+pub fn wrust_should_answer_500_when_a_handler_panics(){
+    // Arrange
+    let app = WRust::with_config(WRustConfig::new().port(0).threads(2));
+    {
+        let mut router = app.router.write().unwrap();
+
+        router.get(String::from("/panic"), Box::new(|_, _| {
+            panic!("handler failed")
+        }));
+
+        router.get(String::from("/"), Box::new(|_, response| {
+            response.text(String::from("ok"))
+        }));
+    }
+
+    let (address, server) = spawn_test_server(app);
+
+    // Act
+    let panicked: Vec<String> = (0..4).map(|_| send_raw(address, "GET /panic HTTP/1.1\r\n\r\n")).collect();
+    let output = send_raw(address, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(panicked.iter().all(|output| output.starts_with("HTTP/1.1 500") && output.contains("Connection: close\r\n")));
+    assert!(output.starts_with("HTTP/1.1 200 OK"));
+    assert!(output.ends_with("ok"));
+}
+
+#[test]
+pub fn wrust_should_read_and_write_chunked_bodies(){
+    // Arrange
+    let app = WRust::with_config(WRustConfig::new().port(0));
+    {
+        let mut router = app.router.write().unwrap();
+
+        router.post(String::from("/json"), Box::new(|request, response| {
+            match request.data {
+                Json(value) => response.json(value),
+                _ => response.status(400)
+            }
+        }));
+
+        router.get(String::from("/stream"), Box::new(|_, response| {
+            response.stream_chunks(["Hello", "", ", ", "World"].into_iter())
+        }));
+    }
+
+    let (address, server) = spawn_test_server(app);
+
+    // Act
+    let output = send_raw(address, concat!(
+        "POST /json HTTP/1.1\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n",
+        "7;ext=1\r\n{\"age\":\r\n3\r\n 42\r\n1\r\n}\r\n0\r\nX-Trailer: ignored\r\n\r\n",
+        "GET /stream HTTP/1.1\r\nConnection: close\r\n\r\n"
+    ));
+    let unframed = send_raw(address, "GET /stream HTTP/1.0\r\nConnection: keep-alive\r\n\r\n");
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(output.contains("Content-Length: 10\r\n"));
+    assert!(output.contains("{\"age\":42}"));
+    assert!(output.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(output.ends_with("\r\n\r\n5\r\nHello\r\n2\r\n, \r\n5\r\nWorld\r\n0\r\n\r\n"));
+    assert!(!unframed.contains("Transfer-Encoding"));
+    assert!(unframed.contains("Connection: close\r\n"));
+    assert!(unframed.ends_with("\r\n\r\nHello, World"));
+}
+
+#[test]
+pub fn wrust_should_refuse_request_bodies_over_the_size_limit(){
+    // Arrange
+    let app = WRust::with_config(WRustConfig::new().port(0).max_body_size(16));
+    app.router.write().unwrap().post(String::from("/echo"), Box::new(|request, response| {
+        response.text(String::from_utf8_lossy(request.body()).to_string())
+    }));
+
+    let (address, server) = spawn_test_server(app);
+    let request = |headers: &str, body: &str| send_raw(address, format!(
+        "POST /echo HTTP/1.1\r\nConnection: close\r\n{}\r\n{}", headers, body
+    ));
+
+    // Act
+    let huge = request("Content-Length: 999999999999999999\r\n", "");
+    let over = request("Content-Length: 17\r\n", "seventeen bytes!!");
+    let invalid = request("Content-Length: 99999999999999999999999\r\n", "");
+    let valid = request("Content-Length: 16\r\n", "sixteen bytes!!!");
+    let huge_chunk = request("Transfer-Encoding: chunked\r\n", "ffffffffffffffff\r\n");
+    let over_chunks = request("Transfer-Encoding: chunked\r\n", "9\r\nfirst 9!!\r\n9\r\nsecond 9!\r\n0\r\n\r\n");
+    let invalid_chunk = request("Transfer-Encoding: chunked\r\n", "fffffffffffffffff\r\n");
+    let valid_chunks = request("Transfer-Encoding: chunked\r\n", "8\r\nsixteen \r\n8\r\nbytes!!!\r\n0\r\n\r\n");
+    let long_size_line = request("Transfer-Encoding: chunked\r\n", &format!("1;{}", "x".repeat(8190)));
+    let long_trailer = request("Transfer-Encoding: chunked\r\n", &format!("0\r\nX-Trailer: {}", "x".repeat(8181)));
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(huge.starts_with("HTTP/1.1 413 Payload Too Large"));
+    assert!(huge.ends_with("Request body larger than 16 bytes"));
+    assert!(over.starts_with("HTTP/1.1 413 Payload Too Large"));
+    assert!(invalid.starts_with("HTTP/1.1 400 Bad Request"));
+    assert!(valid.ends_with("\r\n\r\nsixteen bytes!!!"));
+    assert!(huge_chunk.starts_with("HTTP/1.1 413 Payload Too Large"));
+    assert!(over_chunks.starts_with("HTTP/1.1 413 Payload Too Large"));
+    assert!(invalid_chunk.starts_with("HTTP/1.1 400 Bad Request"));
+    assert!(valid_chunks.ends_with("\r\n\r\nsixteen bytes!!!"));
+    assert!(long_size_line.starts_with("HTTP/1.1 400 Bad Request"));
+    assert!(long_trailer.starts_with("HTTP/1.1 400 Bad Request"));
+}
+
+#[test]
+pub fn response_should_send_binary_bodies(){
+    // Arrange
+    let root = TempDir::new("binary");
+    let path = root.join("image.png");
+    fs::write(&path, [0x89, b'P', b'N', b'G', 0xFF, 0x00]).unwrap();
+
+    let mut bytes_response = Response::new();
+    bytes_response.bytes(Vec::from([0xDE, 0xAD, 0xBE, 0xEF]));
+
+    let mut file_response = Response::new();
+    file_response.file(&path);
+
+    let mut missing_response = Response::new();
+    missing_response.file(path.with_extension("missing"));
+
+    // Act
+    let mut bytes_output = Vec::new();
+    bytes_response.write_to(&mut bytes_output, false).unwrap();
+
+    let mut file_output = Vec::new();
+    file_response.write_to(&mut file_output, false).unwrap();
+
+    // Assert
+    assert!(bytes_output.starts_with(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\nContent-Type: application/octet-stream\r\n"));
+    assert!(bytes_output.ends_with(&[0xDE, 0xAD, 0xBE, 0xEF]));
+    assert!(file_output.starts_with(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\nContent-Type: image/png\r\n"));
+    assert!(file_output.ends_with(&[0x89, b'P', b'N', b'G', 0xFF, 0x00]));
+    assert_eq!(missing_response.get_status(), 404);
+}
+
+#[test]
+pub fn wrust_should_serve_static_files(){
+    // Arrange
+    let root = TempDir::new("static");
+    fs::create_dir_all(root.join("docs")).unwrap();
+    fs::create_dir_all(root.join("empty")).unwrap();
+    fs::write(root.join("app.css"), "body { margin: 0; }").unwrap();
+    fs::write(root.join("docs/index.html"), "<h1>Docs</h1>").unwrap();
+
+    let mut app = WRust::with_config(WRustConfig::new().port(0));
+    app.serve_static("/public/", &root);
+
+    let (address, server) = spawn_test_server(app);
+
+    let request = |path: &str| send_raw(address, format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path));
+
+    // Act
+    let css = request("/public/app.css");
+    let index = request("/public/docs");
+    let empty = request("/public/empty/");
+    let missing = request("/public/missing.js");
+    let traversal = request("/public/docs/../../Cargo.toml");
+    let encoded_traversal = request("/public/%2e%2e/Cargo.toml");
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(css.starts_with("HTTP/1.1 200 OK\r\nContent-Length: 19\r\nContent-Type: text/css\r\n"));
+    assert!(css.ends_with("body { margin: 0; }"));
+    assert!(index.contains("Content-Type: text/html\r\n"));
+    assert!(index.ends_with("<h1>Docs</h1>"));
+    assert!(empty.starts_with("HTTP/1.1 403 Forbidden"));
+    assert!(missing.starts_with("HTTP/1.1 404 Not Found"));
+    assert!(traversal.starts_with("HTTP/1.1 403 Forbidden"));
+    assert!(encoded_traversal.starts_with("HTTP/1.1 403 Forbidden"));
+}
+
+#[test]
+pub fn wrust_should_answer_conditional_requests_with_not_modified(){
+    // Arrange
+    let root = TempDir::new("cache");
+    fs::write(root.join("app.js"), "console.log('cached');").unwrap();
+
+    let mut app = WRust::with_config(WRustConfig::new().port(0));
+    app.mount_static("/assets", StaticFiles::new(&root).cache_control("public, max-age=60"));
+
+    let (address, server) = spawn_test_server(app);
+
+    let request = |headers: &str| send_raw(address, format!("GET /assets/app.js HTTP/1.1\r\nConnection: close\r\n{}\r\n", headers));
+
+    let header = |output: &str, name: &str| {
+        output.lines()
+            .find_map(|line| line.strip_prefix(&format!("{}: ", name)))
+            .map(|value| value.to_string())
+    };
+
+    // Act
+    let first = request("");
+    let etag = header(&first, "ETag").unwrap();
+    let last_modified = header(&first, "Last-Modified").unwrap();
+
+    let by_etag = request(&format!("If-None-Match: \"other\", W/{}\r\n", etag));
+    let by_date = request(&format!("If-Modified-Since: {}\r\n", last_modified));
+    let stale_etag = request(&format!("If-None-Match: \"other\"\r\nIf-Modified-Since: {}\r\n", last_modified));
+    let old_date = request("If-Modified-Since: Thu, 01 Jan 1970 00:00:00 GMT\r\n");
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(first.starts_with("HTTP/1.1 200 OK"));
+    assert_eq!(header(&first, "Cache-Control").as_deref(), Some("public, max-age=60"));
+    assert_eq!(HttpDate::format(HttpDate::parse(&last_modified).unwrap()), last_modified);
+
+    assert!(by_etag.starts_with("HTTP/1.1 304 Not Modified"));
+    assert!(by_etag.ends_with("\r\n\r\n"));
+    assert!(!by_etag.contains("Content-Length"));
+    assert_eq!(header(&by_etag, "ETag"), Some(etag));
+    assert!(by_date.starts_with("HTTP/1.1 304 Not Modified"));
+    assert!(stale_etag.starts_with("HTTP/1.1 200 OK"));
+    assert!(old_date.starts_with("HTTP/1.1 200 OK"));
+}
+
+#[test]
+pub fn wrust_should_answer_range_requests_with_partial_content(){
+    // Arrange
+    let root = TempDir::new("range");
+    fs::write(root.join("video.mp4"), "abcdefghijklmnopqrstuvwxyz").unwrap();
+
+    let mut app = WRust::with_config(WRustConfig::new().port(0));
+    app.serve_static("/media", &root);
+    app.router.write().unwrap().get(String::from("/bytes"), Box::new(|_, response| {
+        response.bytes(Vec::from(*b"0123456789"))
+    }));
+
+    let (address, server) = spawn_test_server(app);
+
+    let request = |path: &str, headers: &str| send_raw(address, format!("GET {} HTTP/1.1\r\nConnection: close\r\n{}\r\n", path, headers));
+
+    // Act
+    let full = request("/media/video.mp4", "");
+    let first = request("/media/video.mp4", "Range: bytes=0-4\r\n");
+    let suffix = request("/bytes", "Range: bytes=-3\r\n");
+    let multiple = request("/media/video.mp4", "Range: bytes=24-,0-1,1-2\r\n");
+    let unsatisfiable = request("/media/video.mp4", "Range: bytes=30-\r\n");
+    let invalid = request("/media/video.mp4", "Range: bytes=5-1\r\n");
+    let changed = request("/media/video.mp4", "Range: bytes=0-4\r\nIf-Range: \"outdated\"\r\n");
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(full.contains("Accept-Ranges: bytes\r\n"));
+    assert!(full.contains("Content-Type: video/mp4\r\n"));
+
+    assert!(first.starts_with("HTTP/1.1 206 Partial Content\r\nContent-Length: 5\r\n"));
+    assert!(first.contains("Content-Range: bytes 0-4/26\r\n"));
+    assert!(first.ends_with("\r\n\r\nabcde"));
+
+    assert!(suffix.contains("Content-Range: bytes 7-9/10\r\n"));
+    assert!(suffix.ends_with("\r\n\r\n789"));
+
+    let boundary = multiple.lines()
+        .find_map(|line| line.strip_prefix("Content-Type: multipart/byteranges; boundary="))
+        .unwrap();
+    let body = multiple.split_once("\r\n\r\n").unwrap().1;
+    let expected = format!(concat!(
+        "--{0}\r\nContent-Type: video/mp4\r\nContent-Range: bytes 0-2/26\r\n\r\nabc",
+        "\r\n--{0}\r\nContent-Type: video/mp4\r\nContent-Range: bytes 24-25/26\r\n\r\nyz",
+        "\r\n--{0}--\r\n"
+    ), boundary);
+
+    assert!(multiple.starts_with("HTTP/1.1 206 Partial Content"));
+    assert!(multiple.contains(&format!("Content-Length: {}\r\n", expected.len())));
+    assert_eq!(body, expected);
+
+    assert!(unsatisfiable.starts_with("HTTP/1.1 416 Range Not Satisfiable"));
+    assert!(unsatisfiable.contains("Content-Range: bytes */26\r\n"));
+    assert!(invalid.starts_with("HTTP/1.1 200 OK"));
+    assert!(changed.starts_with("HTTP/1.1 200 OK"));
+    assert!(changed.ends_with("abcdefghijklmnopqrstuvwxyz"));
+}
+
+#[test]
+pub fn template_should_render_views_with_layouts_and_partials(){
+    // Arrange
+    let root = TempDir::new("views");
+    fs::create_dir_all(root.join("partials")).unwrap();
+    fs::create_dir_all(root.join("people")).unwrap();
+    fs::write(root.join("base.html"), "<title>{% block title %}Site{% endblock %}</title>{% block content %}{% endblock %}").unwrap();
+    fs::write(root.join("layout.html"), "{% extends \"base\" %}{% block content %}<main>{% block main %}{% endblock %}</main>{% endblock %}").unwrap();
+    fs::write(root.join("partials/person.html"), "<li>{{ loop.index }}. {{ person.name | upper }}</li>").unwrap();
+    fs::write(root.join("people/index.html"), concat!(
+        "{# The list of people #}{% extends \"layout\" %}",
+        "{% block title %}{{ title }}{% endblock %}",
+        "{% block main %}",
+        "{% if role == \"admin\" and not banned %}Admin{% elif role %}User{% else %}Guest{% endif %}",
+        "<ul>{% for person in people %}{% include \"partials/person\" %}{% else %}<li>Nobody</li>{% endfor %}</ul>",
+        "{{ bio }}|{{ bio | raw }}|{{ people | length }}|{% for key, value in tags %}{{ key }}={{ value }};{% endfor %}",
+        "{% endblock %}"
+    )).unwrap();
+    fs::write(root.join("broken.html"), "<p>\n{% if user %}\n{{ user.name }}").unwrap();
+    fs::write(root.join("missing-partial.html"), "{% include \"nowhere\" %}").unwrap();
+
+    let views = ViewsDirectory::new(&root);
+    let context = serde_json::json!({
+        "title": "People",
+        "role": "admin",
+        "banned": false,
+        "bio": "<b>Tom & Jerry</b>",
+        "people": [{ "name": "Nora" }, { "name": "Tim" }],
+        "tags": { "a": 1, "b": true }
+    });
+
+    // Act
+    let mut response = Response::new();
+    response.render_with(&views, "people", &context);
+
+    let empty = views.get_template("people").unwrap()
+        .render(&serde_json::json!({ "role": "user", "people": [] }), &views)
+        .unwrap();
+
+    let broken = views.get_template("broken").unwrap_err();
+
+    let mut missing = Response::new();
+    missing.render_with(&views, "nowhere", &context);
+
+    let mut missing_partial = Response::new();
+    missing_partial.render_with(&views, "missing-partial", &context);
+
+
+    let mut output = Vec::new();
+    response.write_to(&mut output, false).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    // Assert
+    assert!(output.contains("Content-Type: text/html\r\n"));
+    assert!(output.ends_with(concat!(
+        "<title>People</title><main>Admin<ul><li>1. NORA</li><li>2. TIM</li></ul>",
+        "&lt;b&gt;Tom &amp; Jerry&lt;/b&gt;|<b>Tom & Jerry</b>|2|a=1;b=true;</main>"
+    )));
+    assert_eq!(empty, "<title></title><main>User<ul><li>Nobody</li></ul>||0|</main>");
+    assert!(matches!(broken, TemplateError::Syntax(name, 2, _) if name == "broken"));
+    assert_eq!(missing.get_status(), 404);
+    assert_eq!(missing_partial.get_status(), 500);
+}
+
+#[test]
+pub fn template_registry_should_cache_views_and_reload_changed_ones(){
+    // Arrange
+    let root = TempDir::new("registry");
+    fs::create_dir_all(root.join("nested")).unwrap();
+    fs::write(root.join("index.html"), "Home").unwrap();
+    fs::write(root.join("nested/index.html"), "Nested {{ name }}").unwrap();
+
+    let cached = TemplateRegistry::new(&root);
+    let reloaded = TemplateRegistry::new(&root).hot_reload(true);
+    let render = |registry: &TemplateRegistry, name: &str| {
+        registry.get_template(name).and_then(|template| template.render(&serde_json::json!({ "name": "view" }), registry))
+    };
+
+    // Act
+    let preloaded = (cached.preload().unwrap(), reloaded.preload().unwrap());
+    let before = (render(&cached, "").unwrap(), render(&reloaded, "nested").unwrap());
+
+    let file = fs::File::options().write(true).truncate(true).open(root.join("nested/index.html")).unwrap();
+    (&file).write_all(b"Changed {{ name }}").unwrap();
+    file.set_modified(UNIX_EPOCH + Duration::from_secs(86400)).unwrap();
+    fs::write(root.join("added.html"), "Added").unwrap();
+
+    let after = (render(&cached, "nested").unwrap(), render(&reloaded, "nested/index").unwrap());
+    let added = render(&cached, "added").unwrap();
+
+    fs::remove_file(root.join("index.html")).unwrap();
+    let removed = (render(&cached, "index").unwrap(), render(&reloaded, "index").unwrap_err());
+
+
+    // Assert
+    assert_eq!(preloaded, (2, 2));
+    assert_eq!(before, (String::from("Home"), String::from("Nested view")));
+    assert_eq!(after, (String::from("Nested view"), String::from("Changed view")));
+    assert_eq!(added, "Added");
+    assert_eq!(removed.0, "Home");
+    assert!(matches!(removed.1, TemplateError::NotFound(_)));
+}
+
+#[test]
+pub fn wrust_should_use_the_configured_views_and_public_directories(){
+    // Arrange
+    let root = TempDir::new("dirs");
+    fs::create_dir_all(root.join("templates")).unwrap();
+    fs::create_dir_all(root.join("assets")).unwrap();
+    fs::write(root.join("templates/hello.html"), "Hello {{ name }}").unwrap();
+    fs::write(root.join("assets/app.js"), "alert(1);").unwrap();
+
+    let config = WRustConfig::new()
+        .port(0)
+        .views_dir(root.join("templates"))
+        .public_dir(root.join("assets"));
+    let executable_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let installed = TempDir::new_in(&executable_dir, "installed");
+    let installed_name = installed.file_name().unwrap();
+
+    let mut app = WRust::with_config(config);
+    app.serve_public("/static");
+    app.router.write().unwrap().get(String::from("/hello"), Box::new(|_, response| {
+        response.render("hello", &serde_json::json!({ "name": "<you>" }))
+    }));
+
+    let (address, server) = spawn_test_server(app);
+
+    let request = |path: &str| send_raw(address, format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path));
+
+    // Act
+    let hello = request("/hello");
+    let script = request("/static/app.js");
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(hello.ends_with("\r\n\r\nHello &lt;you&gt;"));
+    assert!(script.contains("Content-Type: application/javascript\r\n"));
+    assert!(script.ends_with("alert(1);"));
+    assert_eq!(WRustConfig::new().views_dir(installed_name).get_views_dir(), executable_dir.join(installed_name));
+    assert_eq!(WRustConfig::new().public_dir("files/public").get_public_dir(), env::current_dir().unwrap().join("files/public"));
+    assert_eq!(Response::new().render("hello", &serde_json::json!({})).get_status(), 500);
+}
+
+#[test]
+pub fn wrust_should_run_middleware_in_order_around_handlers(){
+    // Arrange
+    let mut app = WRust::with_config(WRustConfig::new().port(0));
+
+    app.middleware(from_fn(|request, response, next| {
+        // Headers can still be changed once the rest of the chain ran
+        next.run(request, response);
+        let trace = response.get_header("X-Trace").cloned().unwrap_or_default();
+
+        response.set_header(String::from("X-Trace"), format!("global>{}", trace));
+        response
+    }));
+
+    {
+        let mut router = app.router.write().unwrap();
+
+        router.middleware(from_fn(|request, response, next| {
+            if request.get_header("Authorization").is_none() {
+                response.status(401);
+                return response.text(String::from("Unauthorized"));
+            }
+
+            next.run(request, response)
+        }));
+
+        router.middleware(from_fn(|request, response, next| {
+            response.set_header(String::from("X-Trace"), String::from("router"));
+            next.run(request, response)
+        }));
+
+        router.add_route(RouteMethod::RouteGet, String::from("/secret"), Box::new(|_, response| {
+            let trace = response.get_header("X-Trace").cloned().unwrap_or_default();
+            response.text(format!("{}>handler", trace))
+        }), Vec::from([from_fn(|request, response, next| {
+            let trace = response.get_header("X-Trace").cloned().unwrap_or_default();
+
+            response.set_header(String::from("X-Trace"), format!("{}>route", trace));
+            next.run(request, response)
+        })]));
+    }
+
+    let (address, server) = spawn_test_server(app);
+
+    let request = |path: &str, headers: &str| send_raw(address, format!("GET {} HTTP/1.1\r\nConnection: close\r\n{}\r\n", path, headers));
+
+    // Act
+    let authorized = request("/secret", "Authorization: Bearer token\r\n");
+    let unauthorized = request("/secret", "");
+    let missing = request("/missing", "");
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(authorized.starts_with("HTTP/1.1 200 OK"));
+    assert!(authorized.contains("X-Trace: global>router>route\r\n"));
+    assert!(authorized.ends_with("router>route>handler"));
+    assert!(unauthorized.starts_with("HTTP/1.1 401 Unauthorized"));
+    assert!(unauthorized.contains("X-Trace: global>\r\n"));
+    assert!(missing.starts_with("HTTP/1.1 404 Not Found"));
+    assert!(missing.contains("X-Trace: global>\r\n"));
+}
+
+#[test]
+pub fn router_should_nest_groups_and_mount_sub_routers(){
+    // Arrange
+    let tag = |name: &'static str| from_fn(move |request, response, next| {
+        let trace = response.get_header("X-Trace").cloned().unwrap_or_default();
+
+        response.set_header(String::from("X-Trace"), format!("{}{}>", trace, name));
+        next.run(request, response)
+    });
+
+    let mut users = Router::new();
+    users.middleware(tag("users"));
+    users.get(String::from("/:id:uint"), Box::new(|request, response| {
+        let trace = response.get_header("X-Trace").cloned().unwrap_or_default();
+
+        match request.params.get("id") {
+            Some(QueryParam { value: Single(UInt(id)) }) => response.text(format!("{}user {}", trace, id)),
+            _ => response.status(400)
+        }
+    }));
+
+    let app = WRust::with_config(WRustConfig::new().port(0));
+    {
+        let mut router = app.router.write().unwrap();
+        router.middleware(tag("root"));
+
+        router.group("/api", |api| {
+            api.middleware(tag("api"));
+
+            api.group("v1/", |v1| {
+                v1.get(String::from("/status"), Box::new(|_, response| {
+                    let trace = response.get_header("X-Trace").cloned().unwrap_or_default();
+                    response.text(format!("{}ok", trace))
+                }));
+            });
+
+            api.mount("/users", users);
+        });
+
+        router.get(String::from("/status"), Box::new(|_, response| response.text(String::from("root"))));
+    }
+
+    let routes_count = app.router.read().unwrap().routes_count();
+    let (address, server) = spawn_test_server(app);
+
+    let request = |path: &str| send_raw(address, format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path));
+
+    // Act
+    let status = request("/api/v1/status");
+    let user = request("/api/users/42");
+    let root = request("/status");
+    let outside = request("/v1/status");
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert_eq!(routes_count, 3);
+    assert!(status.ends_with("root>api>ok"));
+    assert!(user.ends_with("root>api>users>user 42"));
+    assert!(root.ends_with("\r\n\r\nroot"));
+    assert!(outside.starts_with("HTTP/1.1 404 Not Found"));
+}
+
+#[test]
+pub fn route_builder_should_register_handlers_with_metadata(){
+    // Arrange
+    let mut router = Router::new();
+    let noop = from_fn(|request, response, next| next.run(request, response));
+
+    // Act
+    router.route("people?page:uint?")
+        .get(|_, response| response.text(String::from("list")))
+        .post(|_, response| response.text(String::from("create")))
+        .name("people")
+        .description("Everybody")
+        .tags(&["people", "public"])
+        .middleware(noop);
+
+    let builder = router.route("/drafts").get(|_, response| response.text(String::from("draft")));
+    drop(builder);
+
+    router.group("/admin", |admin| {
+        admin.route("/people/:id:uint").method("purge", |_, response| response.status(204)).name("purge").build();
+    });
+
+    // Assert
+    assert_eq!(router.routes_count(), 4);
+    assert!(matches!(router.get_request_endpoint(&POST, "/people"), Ok(Endpoint::Route(..))));
+    assert!(matches!(router.get_request_endpoint(&GET, "/drafts"), Ok(Endpoint::Route(..))));
+    assert!(matches!(router.get_request_endpoint(&HttpMethod::Custom(String::from("PURGE")), "/admin/people/1"), Ok(Endpoint::Route(..))));
+
+    let people = router.get_route_by_name("people").unwrap();
+    assert_eq!(people.path, "/people");
+    assert_eq!(people.metadata.description.as_deref(), Some("Everybody"));
+    assert_eq!(people.metadata.tags, Vec::from([String::from("people"), String::from("public")]));
+    assert_eq!(people.middlewares.len(), 1);
+    assert!(people.queries.contains_key("page"));
+
+    assert_eq!(router.get_route_by_name("purge").unwrap().path, "/admin/people/:id:uint");
+    assert!(router.get_route_by_name("drafts").is_none());
+}
+
+#[test]
+pub fn wrust_should_parse_multipart_form_data_and_save_uploaded_files(){
+    // Arrange
+    let uploads = TempDir::new("uploads");
+    let app = WRust::with_config(WRustConfig::new().port(0).upload_dir(&uploads).max_body_size(64 * 1024));
+
+    {
+        let mut router = app.router.write().unwrap();
+
+        router.post(String::from("/upload"), Box::new(|request, response| {
+            match request.data {
+                Form(mut form_data) => {
+                    form_data.get_file_mut("avatar").unwrap().persist();
+                    let file = form_data.get_file("avatar").unwrap();
+
+                    response.json(serde_json::json!({
+                        "data": form_data.data,
+                        "files": form_data.files.len(),
+                        "name": file.name,
+                        "original_name": file.original_name,
+                        "mime_type": file.mime_type,
+                        "encoding": file.encoding,
+                        "path": file.path,
+                        "size": file.size
+                    }))
+                },
+                _ => response.status(400)
+            }
+        }));
+
+        router.post(String::from("/backup"), Box::new(|request, response| {
+            match request.data {
+                Form(mut form_data) => {
+                    let file = form_data.get_file_mut("archive").unwrap();
+                    let moved = file.persist_to(Path::new(&file.destination).join("backup.bin"));
+
+                    response.text(format!("{:?} {} {}", moved, file.size, file.path))
+                },
+                _ => response.status(400)
+            }
+        }));
+
+        // Files the handler does not persist are deleted once the request ends
+        router.post(String::from("/discard"), Box::new(|request, response| {
+            match request.data {
+                Form(form_data) => response.text(form_data.files.len().to_string()),
+                _ => response.status(400)
+            }
+        }));
+    }
+
+    let (address, server) = spawn_test_server(app);
+
+    let body = concat!(
+        "--XyZ\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nHello, World\r\n",
+        "--XyZ\r\nContent-Disposition: form-data; name=\"tags\"\r\n\r\nrust\r\n",
+        "--XyZ\r\nContent-Disposition: form-data; name=\"tags\"\r\n\r\nweb\r\n",
+        "--XyZ\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"../me.PNG\"\r\nContent-Type: image/png\r\n\r\n\x01\r\n--\x02\r\n",
+        "--XyZ\r\nContent-Disposition: form-data; name=\"empty\"; filename=\"\"\r\nContent-Type: application/octet-stream\r\n\r\n\r\n",
+        "--XyZ--\r\n"
+    );
+    let request = |content_type: &str| send_raw(address, format!(
+        "POST /upload HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        content_type, body.len(), body
+    ));
+
+    // Parts larger than a read, full of near boundaries, are streamed to disk untouched
+    let archive = "\r\n--Xy\r\n-".repeat(4000);
+    let large_body = format!(
+        "--XyZ\r\nContent-Disposition: form-data; name=\"archive\"; filename=\"data.bin\"\r\n\r\n{}\r\n--XyZ--\r\n",
+        archive
+    );
+    let multipart = |path: &str, body: &str| send_raw(address, format!(
+        "POST {} HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=XyZ\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, body.len(), body
+    ));
+
+    // Act
+    let output = request("multipart/form-data; boundary=\"XyZ\"");
+    let missing_boundary = request("multipart/form-data");
+    let backup = multipart("/backup", &large_body);
+    let discarded = multipart("/discard", body);
+    let not_found = multipart("/missing", body);
+    let unterminated = multipart("/upload", &large_body[..large_body.len() - 12]);
+    let too_large = send_raw(address, format!(
+        "POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=XyZ\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n{:x}\r\n{}\r\n10000\r\n",
+        large_body.len() - 12, &large_body[..large_body.len() - 12]
+    ));
+
+    let stopped = server.stop();
+
+    let (_, json) = output.split_once("\r\n\r\n").unwrap();
+    let json: serde_json::Value = serde_json::from_str(json).unwrap();
+    let path = json["path"].as_str().unwrap();
+    let content = fs::read(path).unwrap();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert_eq!(json["data"], serde_json::json!({ "title": "Hello, World", "tags": ["rust", "web"] }));
+    assert_eq!(json["files"], 1);
+    assert_eq!(json["original_name"], "../me.PNG");
+    assert_eq!(json["mime_type"], "image/png");
+    assert_eq!(json["encoding"], "7bit");
+    assert_eq!(json["size"], 6);
+    assert!(json["name"].as_str().unwrap().ends_with(".png"));
+    assert!(Path::new(path).starts_with(&uploads));
+    assert_eq!(content, b"\x01\r\n--\x02");
+    assert!(missing_boundary.starts_with("HTTP/1.1 400"));
+    assert!(backup.ends_with(&format!("\r\n\r\nOk(()) {} {}", archive.len(), uploads.join("backup.bin").display())));
+    assert_eq!(fs::read_to_string(uploads.join("backup.bin")).unwrap(), archive);
+    assert!(discarded.ends_with("\r\n\r\n1"));
+    assert!(not_found.starts_with("HTTP/1.1 404"));
+    assert!(unterminated.starts_with("HTTP/1.1 400"));
+    assert!(too_large.starts_with("HTTP/1.1 413"));
+    assert_eq!(fs::read_dir(&uploads).unwrap().count(), 2);
+}
+
+#[test]
+pub fn wrust_should_parse_and_validate_urlencoded_form_bodies(){
+    // Arrange
+    let app = WRust::with_config(WRustConfig::new().port(0));
+
+    app.router.write().unwrap().route("/people").body("name&age:uint&tags+&nickname?").post(|request, response| {
+        let Form(form_data) = &request.data else {
+            return response.status(400);
+        };
+
+        let age = match request.form_map.get("age").map(|param| &param.value) {
+            Some(Single(UInt(age))) => *age,
+            _ => 0
+        };
+        let tags = match request.form_map.get("tags").map(|param| &param.value) {
+            Some(Multiple(tags)) => tags.len(),
+            _ => 0
+        };
+
+        response.text(format!("{} {} {}", form_data.data["name"].as_str().unwrap_or_default(), age, tags))
+    });
+
+    let (address, server) = spawn_test_server(app);
+
+    let request = |body: &str| send_raw(address, format!(
+        "POST /people HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(), body
+    ));
+
+    // Act
+    let valid = request("name=Jos%C3%A9+Doe&age=42&tags=a&tags=b%26c");
+    let invalid = request("name=Jane&age=old");
+    let malformed = request("name=%E");
+    let latin1 = request("name=caf%E9&age=1&tags=a");
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(valid.ends_with("\r\n\r\nJosé Doe 42 2"));
+    assert!(invalid.starts_with("HTTP/1.1 400"));
+    assert!(invalid.contains("\"name\":\"body\""));
+    assert!(invalid.contains("\"age\":\"age is required\""));
+    assert!(invalid.contains("\"tags\":\"tags can not be empty\""));
+    assert!(!invalid.contains("nickname"));
+    assert!(malformed.starts_with("HTTP/1.1 400"));
+    assert!(latin1.starts_with("HTTP/1.1 400"));
+    // Paths fall back to one char per byte, only form fields have to be UTF-8
+    assert_eq!(UrlEncoding::url_decode(String::from("/caf%E9")), Ok(String::from("/caf\u{e9}")));
+    assert_eq!(UrlEncoding::url_decode(String::from("/caf%C3%A9")), Ok(String::from("/café")));
+    assert_eq!(UrlEncoding::percent_decode("a+%2Fé"), Ok(Vec::from("a+/é".as_bytes())));
+    assert!(UrlEncoding::percent_decode("%2").is_err());
+    assert!(UrlEncoding::percent_decode("%+1").is_err());
+    assert!(UrlEncoding::url_decode_utf8(String::from("caf%E9")).is_err());
+}
+
+#[test]
+pub fn request_should_expose_the_raw_body_for_every_content_type_and_method(){
+    // Arrange
+    let app = WRust::with_config(WRustConfig::new().port(0));
+
+    app.router.write().unwrap().route("/echo").all(|request, response| {
+        let data = match &request.data {
+            Json(value) => format!("json {}", value),
+            Text(text) => format!("text {:?}", text),
+            Form(_) => String::from("form")
+        };
+
+        response.text(format!("{} {} {}", request.method.as_str(), request.body().len(), data))
+    });
+
+    let (address, server) = spawn_test_server(app);
+
+    let request = |method: &str, content_type: &str, body: &[u8]| {
+        let head = format!(
+            "{} /echo HTTP/1.1\r\ncontent-type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            method, content_type, body.len()
+        );
+
+        send_raw(address, [head.as_bytes(), body].concat())
+    };
+
+    // Act
+    let json = request("PUT", "application/json; charset=utf-8", b"{\"age\":42}");
+    let problem = request("PATCH", "application/problem+json", b"[1]");
+    let csv = request("DELETE", "text/csv", b"a,b\n1,2");
+    let binary = request("POST", "application/octet-stream", &[0, 159, 146, 150]);
+
+    let stopped = server.stop();
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(json.ends_with("\r\n\r\nPUT 10 json {\"age\":42}"));
+    assert!(problem.ends_with("\r\n\r\nPATCH 3 json [1]"));
+    assert!(csv.ends_with("\r\n\r\nDELETE 7 text \"a,b\\n1,2\""));
+    assert!(binary.ends_with("\r\n\r\nPOST 4 text \"\""));
+}
+
+#[test]
+pub fn request_should_deserialize_typed_json_bodies_and_list_invalid_fields(){
+    // Arrange
+    #[derive(serde::Deserialize)]
+    struct Address {
+        city: String
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Signup {
+        #[serde(alias = "full_name")]
+        name: String,
+        age: u8,
+        #[serde(default)]
+        tags: Vec<String>,
+        address: Address,
+        nickname: Option<String>,
+        #[serde(default)]
+        visits: u128
+    }
+
+    let app = WRust::with_config(WRustConfig::new().port(0));
+
+    app.router.write().unwrap().route("/signup").post(|request, response| {
+        match request.json::<Signup>() {
+            Ok(signup) => response.text(format!(
+                "{} {} {} {} {:?} {}", signup.name, signup.age, signup.tags.join(","), signup.address.city, signup.nickname, signup.visits
+            )),
+            Err(err) => {
+                response.status(err.get_status());
+                response.json(err.get_errors())
+            }
+        }
+    });
+
+    let (address, server) = spawn_test_server(app);
+
+    let request = |body: &str| send_raw(address, format!(
+        "POST /signup HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(), body
+    ));
+
+    // Act
+    let valid = request(r#"{"name":"Jane","age":30,"tags":["a","b"],"address":{"city":"Paris"},"visits":18446744073709551615}"#);
+    let defaults = request(r#"{"full_name":"Jane","age":30,"address":{"city":"Paris"}}"#);
+    let missing = request(r#"{"age":30,"address":{"city":"Paris"}}"#);
+    let missing_nested = request(r#"{"name":"Jane","age":30,"address":{}}"#);
+    let out_of_range = request(r#"{"name":"Jane","age":300,"address":{"city":"Paris"}}"#);
+    let invalid_item = request(r#"{"name":"Jane","age":30,"tags":["a",2],"address":{"city":"Paris"}}"#);
+    let malformed = request(r#"{"name":"#);
+
+    let stopped = server.stop();
+
+    let errors = |output: &str| -> serde_json::Value {
+        serde_json::from_str(output.split_once("\r\n\r\n").unwrap().1).unwrap()
+    };
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(valid.ends_with("\r\n\r\nJane 30 a,b Paris None 18446744073709551615"));
+    assert!(defaults.ends_with("\r\n\r\nJane 30  Paris None 0"));
+    assert!(missing.starts_with("HTTP/1.1 422 Unprocessable Content"));
+    assert_eq!(errors(&missing), serde_json::json!({ "body": "missing field `name`" }));
+    assert_eq!(errors(&missing_nested), serde_json::json!({ "address": "missing field `city`" }));
+    assert!(errors(&out_of_range)["age"].as_str().unwrap().contains("300"));
+    assert!(errors(&invalid_item)["tags[1]"].as_str().unwrap().contains("expected a string"));
+    assert!(malformed.starts_with("HTTP/1.1 400"));
+    assert!(malformed.contains("Invalid Json"));
+}
+
+#[test]
+pub fn wrust_should_validate_query_path_and_body_fields_against_rules(){
+    // Arrange
+    #[derive(serde::Deserialize, inject_struct::InjectStruct)]
+    struct Member {
+        #[serde(rename = "mail")]
+        #[validate(email)]
+        email: String,
+        #[validate(in = "admin,user")]
+        role: String,
+        #[validate(min = 3, max = 20)]
+        nickname: String,
+        #[validate(min = 18, max = 120)]
+        age: usize
+    }
+
+    let app = WRust::with_config(WRustConfig::new().port(0));
+    {
+        let mut router = app.router.write().unwrap();
+
+        router.route("/teams/:id:uint|min=1?name|len=2..10&code?|regex=^[A-Z]{3}$|^X$&scores:int*|min=0&tag?|max=4").get(|_, response| {
+            response.text(String::from("ok"))
+        });
+
+        router.route(r"/words/:word|regex=^(colou?r)$?ratio|regex=^(\d+/\d+)$&pair?|regex=^(a&b)$").get(|_, response| {
+            response.text(String::from("ok"))
+        });
+
+        router.route("/members").post(|request, response| {
+            match request.validated_json::<Member>() {
+                Ok(member) => response.text(format!("{} {} {} {}", member.email, member.role, member.nickname, member.age)),
+                Err(err) => {
+                    response.status(err.get_status());
+                    response.json(err.get_errors())
+                }
+            }
+        });
+    }
+
+    let mut invalid = WRust::with_config(WRustConfig::new().port(0));
+    {
+        let mut router = invalid.router.write().unwrap();
+
+        router.route("/broken?age:uint|min=old").get(|_, response| response.status(204));
+        router.get(String::from("/regex/:id|regex=(["), Box::new(|_, response| response.status(204)));
+    }
+
+    let (address, server) = spawn_test_server(app);
+
+    let request = |head: &str, body: &str| send_raw(address, format!(
+        "{} HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        head, body.len(), body
+    ));
+    let errors = |output: &str| -> serde_json::Value {
+        serde_json::from_str(output.split_once("\r\n\r\n").unwrap().1).unwrap()
+    };
+
+    // Act
+    let valid_query = request("GET /teams/3?name=Rust&code=X&scores=1&scores=2&tag=rust", "");
+    let invalid_query = request("GET /teams/3?name=R&code=abc&scores=1&scores=-2&tag=rustacean", "");
+    let invalid_path = request("GET /teams/0?name=Rust", "");
+    let mistyped_path = request("GET /teams/abc?name=Rust", "");
+    let grouped_regex = request("GET /words/color?ratio=1/2", "");
+    let invalid_grouped_path = request("GET /words/colr?ratio=1/2", "");
+    let invalid_grouped_query = request("GET /words/colour?ratio=12", "");
+    let valid_body = request("POST /members", r#"{"mail":"jane@example.com","role":"admin","nickname":"jdoe","age":30}"#);
+    let invalid_body = request("POST /members", r#"{"mail":"jane","role":"root","nickname":"j","age":12}"#);
+
+    let stopped = server.stop();
+
+    let query_errors = errors(&invalid_query)["errors"].clone();
+    let body_errors = errors(&invalid_body);
+
+    // Assert
+    assert!(stopped.is_ok());
+    assert!(valid_query.ends_with("\r\n\r\nok"));
+    assert!(invalid_query.starts_with("HTTP/1.1 400"));
+    assert_eq!(query_errors["name"], "name must be between 2 and 10 characters long");
+    assert_eq!(query_errors["code"], "code must match ^[A-Z]{3}$|^X$");
+    assert_eq!(query_errors["scores"], "scores must be at least 0");
+    assert_eq!(query_errors["tag"], "tag must be at most 4 characters long");
+    assert!(invalid_path.starts_with("HTTP/1.1 400"));
+    assert!(invalid_path.contains("\"id\":\"id must be at least 1\""));
+    assert!(mistyped_path.starts_with("HTTP/1.1 400"));
+    assert!(mistyped_path.contains("\"id\":\"id is required\""));
+    assert!(grouped_regex.ends_with("\r\n\r\nok"));
+    assert!(invalid_grouped_path.contains("\"word\":\"word must match ^(colou?r)$\""));
+    assert!(invalid_grouped_query.contains(r#""ratio":"ratio must match ^(\\d+/\\d+)$""#));
+    assert_eq!(invalid.router.read().unwrap().routes_count(), 0);
+    assert_eq!(invalid.router.read().unwrap().get_errors().len(), 2);
+    assert!(matches!(invalid.listen(), Err(ServerError::InvalidRoutes(errors)) if errors.iter().any(|err| err.starts_with("/broken?age:uint|min=old: "))));
+    assert!(valid_body.ends_with("\r\n\r\njane@example.com admin jdoe 30"));
+    assert!(invalid_body.starts_with("HTTP/1.1 422"));
+    assert_eq!(body_errors["mail"], "mail must be an email address");
+    assert_eq!(body_errors["role"], "role must be one of admin, user");
+    assert_eq!(body_errors["age"], "age must be at least 18");
+    assert_eq!(body_errors["nickname"], "nickname must be at least 3 characters long");
+}
@@ -1,12 +1,23 @@
 use std::cmp::max;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 // This is what we will send through the channel to handle the request
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+// Workers either get a job to run, or are told to stop once the jobs queued before are done
+enum Message {
+    NewJob(Job),
+    Terminate
+}
+
+// How often shutdown checks whether the workers are done
+const SHUTDOWN_INTERVAL: Duration = Duration::from_millis(10);
+
 // The worker will have an id and JoinHandle to drop the thread when finished
 struct Worker{
     id: usize,
@@ -16,19 +27,30 @@ struct Worker{
 // This is the group of threads we launched, and the channel sender
 pub struct ThreadPool{
     workers: Vec<Worker>,
-    sender: Sender<Job>
+    sender: Sender<Message>,
+    terminated: bool
 }
 
 impl Worker{
     // Here we pass an id, and the channel receiver cloned by the Arc, and contained in the Mutex
-    fn new(id: usize, receiver: Arc<Mutex<Receiver<Job>>>) -> Worker {
+    fn new(id: usize, receiver: Arc<Mutex<Receiver<Message>>>) -> Worker {
         let thread = Some(thread::spawn(move || loop {
-            // Lock the mutex so one thread handles a job at once
-            let job = receiver.lock().unwrap().recv().unwrap();
-
-            println!("Worker Id {id} Got Job. Executing...");
-
-            job();
+            // Lock the mutex so one thread handles a job at once, the lock is released before running it
+            let message = match receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(_) => break
+            };
+
+            match message {
+                Ok(Message::NewJob(job)) => {
+                    // A panicking job must not take the worker down with it
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        eprintln!("Worker {id} recovered from a panicking job");
+                    }
+                },
+                // Either asked to stop, or the pool is gone and no job can ever come
+                Ok(Message::Terminate) | Err(_) => break
+            }
         }));
 
         Worker{
@@ -40,9 +62,7 @@ impl Worker{
 
 impl ThreadPool{
     /// Create a new ThreadPool.
-    /// The size is the number of threads in the pool.
-    /// # Panics
-    /// The `new` function will panic if the size is zero.
+    /// The size is the number of threads in the pool, at least one thread is always created.
     pub fn new(mut size: usize) -> ThreadPool{
         size = max(size, 1);
 
@@ -60,7 +80,7 @@ impl ThreadPool{
             workers.push(Worker::new(id, Arc::clone(&receiver)));
         }
 
-        ThreadPool { workers, sender }
+        ThreadPool { workers, sender, terminated: false }
     }
 
     pub fn execute<F>(&self, f: F)
@@ -72,18 +92,77 @@ impl ThreadPool{
         let job = Box::new(f);
 
         // Send the job to the available thread (availability depends on OS Scheduler)
-        self.sender.send(job).unwrap();
+        if self.sender.send(Message::NewJob(job)).is_err() {
+            eprintln!("The thread pool is shut down, the job is dropped");
+        }
+    }
+
+    /// Let the workers finish the queued jobs, then stop them.
+    /// Workers still busy after the timeout are left to finish on their own.
+    /// Returns whether every worker stopped in time.
+    pub fn shutdown(&mut self, timeout: Duration) -> bool {
+        self.terminate();
+
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            for worker in &mut self.workers {
+                let is_finished = worker.thread.as_ref().is_some_and(|thread| thread.is_finished());
+
+                if is_finished {
+                    println!("Shutting down worker {}", worker.id);
+
+                    if let Some(thread) = worker.thread.take() {
+                        let _ = thread.join();
+                    }
+                }
+            }
+
+            let running = self.workers.iter().filter(|worker| worker.thread.is_some()).count();
+
+            if running == 0 {
+                return true;
+            }
+
+            if Instant::now() >= deadline {
+                eprintln!("{} worker(s) still busy after {:?}, leaving them behind", running, timeout);
+
+                // Dropping the handles detaches the threads
+                for worker in &mut self.workers {
+                    worker.thread.take();
+                }
+
+                return false;
+            }
+
+            thread::sleep(SHUTDOWN_INTERVAL);
+        }
+    }
+
+    fn terminate(&mut self) {
+        if self.terminated {
+            return;
+        }
+
+        // One message per worker, each one stops after receiving its own
+        for _ in &self.workers {
+            let _ = self.sender.send(Message::Terminate);
+        }
+
+        self.terminated = true;
     }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        for worker in &mut self.workers {
-            println!("Shutting down worker {}", worker.id);
+        self.terminate();
 
+        for worker in &mut self.workers {
             if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
+                println!("Shutting down worker {}", worker.id);
+
+                let _ = thread.join();
             }
         }
     }
-}
\ No newline at end of file
+}
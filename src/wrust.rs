@@ -1,166 +1,548 @@
-use std::io::Write;
-use std::net::{TcpListener};
-use std::sync::{Arc, Mutex, RwLock};
-use shared::constants::{DEFAULT_STATUS_CODE, STATUS_CODES_MAP};
-use shared::request::{Request};
+use std::io;
+use std::io::BufReader;
+use std::path::Path;
+use std::panic::{self, AssertUnwindSafe};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use shared::constants::{ALLOW_HEADER, CONNECTION_HEADER, KEEP_ALIVE_HEADER};
+use shared::conditional::Conditions;
+use shared::cookie::CookieKey;
+use shared::form_data::UploadConfig;
+use shared::error::ServerError;
+use shared::middleware::{Middleware, Next};
+use shared::range::RangeRequest;
+use shared::request::{HttpMethod, Request};
 use shared::response::Response;
-use crate::router::Router;
+use shared::template::TemplateRegistry;
+use shared::route::RouteMethod;
+use crate::config::WRustConfig;
+use crate::router::{Endpoint, RouteError, Router};
+use crate::shutdown::ShutdownHandle;
+use crate::static_files::StaticFiles;
 use crate::thread_pool::ThreadPool;
 
-const CRLF: &str = "\r\n";
+// How long the accept loop sleeps when no listener has a pending connection
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(10);
 
-static USED_PORTS: Mutex<Vec<u16>> = Mutex::new(Vec::new());
+// A connection waiting for its next request. It is watched by the accept loop, so it does not hold a worker.
+struct IdleConnection {
+    reader: BufReader<TcpStream>,
+    served: usize,
+    since: Instant
+}
+
+// Everything a worker needs to serve a connection
+struct ConnectionContext {
+    router: Arc<RwLock<Router>>,
+    cookie_key: Option<Arc<CookieKey>>,
+    shutdown: ShutdownHandle,
+    templates: Arc<TemplateRegistry>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    uploads: Arc<UploadConfig>,
+    max_body_size: usize,
+    keep_alive_timeout: Duration,
+    max_requests: usize,
+    // Where workers hand kept alive connections back once their requests are served
+    idle: Sender<IdleConnection>
+}
 
 pub struct WRust{
     pub router: Arc<RwLock<Router>>,
-    port: u16
+    config: WRustConfig,
+    listeners: Vec<TcpListener>,
+    cookie_key: Option<Arc<CookieKey>>,
+    templates: Arc<TemplateRegistry>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    shutdown: ShutdownHandle
+}
+
+impl Default for WRust {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WRust {
     pub fn new() -> Self {
+        Self::with_config(WRustConfig::new())
+    }
+
+    pub fn with_config(config: WRustConfig) -> Self {
+        let templates = TemplateRegistry::new(config.get_views_dir()).hot_reload(config.hot_reload);
+
         WRust {
             router: Arc::new(RwLock::new(Router::new())),
-            port: 8080
+            config,
+            listeners: Vec::new(),
+            cookie_key: None,
+            templates: Arc::new(templates),
+            middlewares: Vec::new(),
+            shutdown: ShutdownHandle::new()
+        }
+    }
+
+    /// Set the application secret used to sign and encrypt cookies
+    pub fn secret(&mut self, secret: &str) -> &mut Self {
+        self.cookie_key = Some(Arc::new(CookieKey::from_secret(secret)));
+        self
+    }
+
+    /// Sign and encrypt cookies with a key generated for this process only
+    pub fn random_secret(&mut self) -> &mut Self {
+        self.cookie_key = Some(Arc::new(CookieKey::generate()));
+        self
+    }
+
+    /// Run a middleware around every request, before the middleware of the router and of the routes.
+    /// It also runs for requests answered with a 404, a 405 or the implicit OPTIONS response.
+    pub fn middleware(&mut self, middleware: Arc<dyn Middleware>) -> &mut Self {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// Serve the files of a directory under a path prefix, see `Router::serve_static`
+    pub fn serve_static<P: AsRef<Path>>(&mut self, prefix: &str, dir: P) -> &mut Self {
+        self.mount_static(prefix, StaticFiles::new(dir))
+    }
+
+    /// Serve the configured public directory under a path prefix
+    pub fn serve_public(&mut self, prefix: &str) -> &mut Self {
+        self.serve_static(prefix, self.config.get_public_dir())
+    }
+
+    pub fn get_config(&self) -> &WRustConfig {
+        &self.config
+    }
+
+    pub fn mount_static(&mut self, prefix: &str, static_files: StaticFiles) -> &mut Self {
+        if let Ok(mut router) = self.router.write() {
+            router.mount_static(prefix, static_files);
         }
+
+        self
+    }
+
+    /// The views rendered by `Response::render`, parsed once when the server starts listening
+    pub fn templates(&self) -> Arc<TemplateRegistry> {
+        Arc::clone(&self.templates)
     }
 
-    pub fn listen(&mut self) -> Result<(), String> {
-        // Bind the port
-        if let Some((port, listener)) = Self::get_available_port() {
-            // Create a pool of 4 threads to handle requests
-            let pool = ThreadPool::new(4);
+    /// A handle to stop the server from another thread
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
 
-            USED_PORTS.lock()
-                .unwrap()
-                .push(port);
+    /// Shut the server down gracefully on SIGINT (Ctrl+C), SIGTERM and SIGHUP.
+    /// Signal handlers are process wide, so this can only be called once.
+    pub fn shutdown_on_signals(&self) -> Result<(), ServerError> {
+        let shutdown = self.shutdown_handle();
 
-            self.port = port.clone();
+        ctrlc::set_handler(move || {
+            println!("Termination signal received, shutting down...");
+            shutdown.shutdown();
+        }).map_err(|err| ServerError::SignalHandler(err.to_string()))
+    }
 
-            {
-                let router = Arc::clone(&self.router);
-                if let Ok(mut router) = router.write() {
-                    router.start_listening();
-                };
+    /// The addresses the server is bound to, with the actual port when an ephemeral one was asked for
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners.iter()
+            .filter_map(|listener| listener.local_addr().ok())
+            .collect()
+    }
+
+    /// Bind every configured address, without accepting connections yet
+    pub fn bind(&mut self) -> Result<Vec<SocketAddr>, ServerError> {
+        if self.listeners.is_empty() {
+            self.listeners = match self.config.port {
+                Some(port) => Self::bind_addresses(&self.config.get_addresses(), port)?,
+                None => self.bind_available_port()?
+            };
+        }
+
+        Ok(self.local_addrs())
+    }
+
+    pub fn listen(&mut self) -> Result<(), ServerError> {
+        if let Ok(router) = self.router.read() {
+            if !router.get_errors().is_empty() {
+                return Err(ServerError::InvalidRoutes(router.get_errors().to_vec()));
             }
+        }
 
-            println!("Server is listening at {}", port);
-
-            // Listening for incoming TcpStream Requests
-            for stream in listener.incoming() {
-                // Here we try to get the TcpStream Struct or Panic if error using unwrap
-                let mut stream = stream.unwrap();
-                let router_wrapper = Arc::clone(&self.router);
-
-                // Handle The request
-                pool.execute(move || {
-                    let response = &mut Response::new();
-
-                    match Request::read_request_data(&stream) {
-                        Ok(mut request) => {
-                            match router_wrapper.read() {
-                                Ok(router) => {
-                                    match router.get_request_endpoint(request.method.clone(), &request.path)  {
-                                        Ok(route) => {
-                                            match request.map_queries(&route.queries) {
-                                                Ok(_) => {
-                                                    match route.controller.read() {
-                                                        Ok(controller) => {
-                                                            controller(request, response);
-                                                        },
-                                                        Err(err) => {
-                                                            response.status(500);
-                                                            response.text(err.to_string());
-                                                        }
-                                                    }
-                                                },
-                                                Err(err) => {
-                                                    response.status(400);
-                                                    response.json(err);
-                                                }
-                                            }
-                                        },
-                                        Err(err) => {
-                                            response.status(404);
-                                            response.text(err);
-                                        }
-                                    }
-                                },
-                                Err(err) => {
-                                    response.status(500);
-                                    response.text(err.to_string());
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            response.status(400);
-                            response.text(err);
+        let addresses = self.bind()?;
+
+        let views = self.templates.preload().map_err(ServerError::Template)?;
+        println!("Loaded {} views from {}", views, self.templates.get_root().display());
+
+        // Create the pool of threads handling requests
+        let mut pool = ThreadPool::new(self.config.threads);
+
+        {
+            let router = Arc::clone(&self.router);
+            if let Ok(mut router) = router.write() {
+                router.start_listening();
+            };
+        }
+
+        // Listeners are polled so a single thread can serve several addresses
+        for (listener, address) in self.listeners.iter().zip(&addresses) {
+            listener.set_nonblocking(true).map_err(|err| ServerError::Listen(*address, err))?;
+
+            println!("Server is listening at {}", address);
+        }
+
+        // Connections wait for their requests here, new ones included, and get a worker once a request arrives
+        let (idle_sender, idle_receiver) = channel();
+        let mut idle = Vec::new();
+
+        // Shared by every connection, the upload directory is resolved once
+        let context = Arc::new(self.get_connection_context(idle_sender));
+
+        // Listening for incoming TcpStream Requests
+        while !self.shutdown.is_shutdown() {
+            let mut accepted = false;
+
+            for listener in &self.listeners {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        accepted = true;
+
+                        match IdleConnection::new(stream) {
+                            Ok(connection) => idle.push(connection),
+                            Err(err) => eprintln!("Failed to configure the connection: {:?}", err)
                         }
-                    };
+                    },
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => (),
+                    Err(err) => eprintln!("Failed to accept a connection: {:?}", err)
+                }
+            }
 
-                    let content = response.get_data();
-                    let content_length = content.bytes().len();
-                    let content_type = response.get_content_type();
-                    let status_code = response.get_status();
-                    let status_code_description = Self::get_status_code_description(status_code.clone());
+            idle.extend(idle_receiver.try_iter());
 
-                    let res_status = format!("HTTP/1.1 {} {}", status_code, status_code_description);
-                    let response = format!("{res_status}{CRLF}Content-Length: {content_length}{CRLF}Content-Type: {content_type}{CRLF}{CRLF}{content}");
-                    stream.write_all(response.as_bytes()).unwrap();
-                });
+            let dispatched = Self::dispatch_ready(&mut idle, &pool, &context);
 
-                // When connection received and no error is there we print this 💩
-                println!("Connection established!");
+            if !accepted && !dispatched {
+                thread::sleep(ACCEPT_INTERVAL);
             }
+        }
+
+        // Idle connections are closed, the ones with a request in flight are closed by their worker
+        idle.clear();
+
+        // Stop accepting before waiting for the requests in flight
+        self.listeners.clear();
 
-            return Ok(());
+        if !pool.shutdown(self.config.shutdown_timeout) {
+            eprintln!("Some requests did not finish within {:?}", self.config.shutdown_timeout);
         }
 
-        Err(String::from("No port is available in this range [8080, 8091]"))
+        println!("Server stopped");
+        Ok(())
     }
 
-    fn get_available_port() -> Option<(u16, TcpListener)>{
-        for port in 8080..8091 {
-            if let Ok(ports) = USED_PORTS.lock() {
-                if ports.contains(&port) {
-                    continue;
+    // Hand the idle connections whose next request started arriving to the workers,
+    // and close the ones idle for longer than the keep alive timeout.
+    // Returns whether a connection was handed to a worker.
+    fn dispatch_ready(idle: &mut Vec<IdleConnection>, pool: &ThreadPool, context: &Arc<ConnectionContext>) -> bool {
+        let mut dispatched = false;
+        let mut index = 0;
+
+        while index < idle.len() {
+            match idle[index].is_ready(context.keep_alive_timeout) {
+                Some(true) => {
+                    let connection = idle.swap_remove(index);
+                    let context = Arc::clone(context);
+
+                    // Handle the requests of the connection
+                    pool.execute(move || {
+                        Self::handle_connection(connection, context);
+                    });
+
+                    dispatched = true;
+                },
+                Some(false) => index += 1,
+                // Dropping the connection closes it
+                None => {
+                    idle.swap_remove(index);
                 }
             }
+        }
 
-            let listener = Self::port_is_available(port);
+        dispatched
+    }
 
-            if listener.is_some() {
-                return Some((port, listener.unwrap()));
-            }
+    fn get_connection_context(&self, idle: Sender<IdleConnection>) -> ConnectionContext {
+        ConnectionContext {
+            router: Arc::clone(&self.router),
+            cookie_key: self.cookie_key.clone(),
+            templates: Arc::clone(&self.templates),
+            middlewares: self.middlewares.clone(),
+            uploads: Arc::new(UploadConfig::new(self.config.get_upload_dir())),
+            max_body_size: self.config.max_body_size,
+            shutdown: self.shutdown.clone(),
+            keep_alive_timeout: self.config.keep_alive_timeout,
+            max_requests: self.config.max_requests_per_connection,
+            idle
         }
+    }
+
+    fn handle_connection(connection: IdleConnection, context: Arc<ConnectionContext>) {
+        let IdleConnection { reader: mut buf_reader, mut served, .. } = connection;
+
+        // A started request gets the whole timeout to arrive
+        let stream = buf_reader.get_ref();
+
+        if let Err(err) = stream.set_nonblocking(false).and_then(|_| stream.set_read_timeout(Some(context.keep_alive_timeout))) {
+            eprintln!("Failed to configure the connection: {:?}", err);
+            return;
+        }
+
+        loop {
+            served += 1;
+
+            let response = &mut Response::new();
+            response.set_templates(Arc::clone(&context.templates));
+            let (is_head, keep_alive) = Self::handle_request(&mut buf_reader, response, &context);
+            let closed_by_handler = response.get_header(CONNECTION_HEADER).is_some_and(|value| value.eq_ignore_ascii_case("close"));
+            let keep_alive = keep_alive && !closed_by_handler && !response.is_close_delimited() && served < context.max_requests && !context.shutdown.is_shutdown();
+
+            response.set_header(String::from(CONNECTION_HEADER), String::from(if keep_alive { "keep-alive" } else { "close" }));
+
+            if keep_alive {
+                let timeout = context.keep_alive_timeout.as_secs();
+                let remaining = context.max_requests - served;
+
+                response.set_header(String::from(KEEP_ALIVE_HEADER), format!("timeout={}, max={}", timeout, remaining));
+            }
 
-        None
+            if let Err(err) = response.write_to(buf_reader.get_mut(), is_head) {
+                eprintln!("Failed to write the response: {:?}", err);
+                break;
+            }
+
+            if !keep_alive {
+                break;
+            }
+
+            // Pipelined requests already buffered are served right away, otherwise the worker is freed while waiting
+            if buf_reader.buffer().is_empty() {
+                match IdleConnection::resume(buf_reader, served) {
+                    Ok(connection) => {
+                        let _ = context.idle.send(connection);
+                    },
+                    Err(err) => eprintln!("Failed to configure the connection: {:?}", err)
+                }
+
+                break;
+            }
+        }
     }
 
-    fn port_is_available(port: u16) -> Option<TcpListener> {
-        match TcpListener::bind(("192.168.1.55", port)) {
-            Ok(listener) => Some(listener),
-            _ => None
+    // Handle a single request, returning whether it is a HEAD request and whether the connection can be reused
+    fn handle_request(buf_reader: &mut BufReader<TcpStream>, response: &mut Response, context: &ConnectionContext) -> (bool, bool) {
+        let mut is_head = false;
+        let mut keep_alive = false;
+
+        if let Some(key) = &context.cookie_key {
+            response.cookies().set_key(Arc::clone(key));
         }
+
+        match Request::read_request_data(buf_reader, &context.uploads, context.max_body_size) {
+            Ok(mut request) => {
+                if let Some(key) = &context.cookie_key {
+                    request.cookies.set_key(Arc::clone(key));
+                }
+
+                is_head = request.method == HttpMethod::HEAD;
+                keep_alive = request.is_keep_alive();
+                response.set_chunked(request.http_version != "HTTP/1.0");
+
+                let conditions = Conditions::from_request(&request);
+                let range = RangeRequest::from_request(&request);
+
+                // Global middleware runs for every request, even the ones no route answers
+                let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+                    Next::new(&context.middlewares, &|request, response| Self::dispatch(request, response, context)).run(request, response);
+                }));
+
+                match handled {
+                    Ok(_) => {
+                        if !conditions.apply(response) {
+                            range.apply(response);
+                        }
+                    },
+                    // A panicking handler answers 500 instead of taking the worker down, whatever it wrote is dropped
+                    Err(_) => {
+                        keep_alive = false;
+
+                        *response = Response::new();
+                        response.status(500);
+                        response.text(String::from("Internal Server Error"));
+                    }
+                }
+            }
+            Err(err) => {
+                response.status(err.get_status());
+                response.text(err.to_string());
+            }
+        };
+
+        (is_head, keep_alive)
     }
 
-    fn get_status_code_description(status_code: usize) -> String {
-        match STATUS_CODES_MAP.get(&status_code) {
-            Some(&result) => {
-                String::from(result)
+    // Find the endpoint of the request and run it, with the middleware of the router and of the route
+    fn dispatch<'a>(mut request: Request, response: &'a mut Response, context: &ConnectionContext) -> &'a Response {
+        let router = match context.router.read() {
+            Ok(router) => router,
+            Err(err) => {
+                response.status(500);
+                return response.text(err.to_string());
+            }
+        };
+
+        match router.get_request_endpoint(&request.method, &request.path)  {
+            Ok(Endpoint::Options(methods)) => {
+                let allow = Self::get_allow_header(&methods);
+
+                response.set_header(String::from(ALLOW_HEADER), allow.clone());
+                response.text(allow)
             },
-            None => {
-                let mut result = DEFAULT_STATUS_CODE;
+            Ok(Endpoint::Route(route, params)) => {
+                request.params = params;
+
+                if let Err(err) = request.validate_path(&route.get_params()) {
+                    response.status(err.get_status());
+                    return response.json(err);
+                }
 
-                if status_code > 199 && status_code < 600 {
-                    let status_code = (status_code / 100) * 100;
+                if let Err(err) = request.map_queries(&route.queries) {
+                    response.status(400);
+                    return response.json(err);
+                }
+
+                if let Err(err) = request.map_form(&route.body) {
+                    response.status(400);
+                    return response.json(err);
+                }
 
-                    if let Some(&description) = STATUS_CODES_MAP.get(&status_code) {
-                        result = description;
+                match route.controller.read() {
+                    Ok(controller) => {
+                        let middlewares = router.get_middlewares().iter()
+                            .chain(&route.middlewares)
+                            .cloned()
+                            .collect::<Vec<Arc<dyn Middleware>>>();
+
+                        Next::new(&middlewares, controller.as_ref()).run(request, response)
+                    },
+                    Err(err) => {
+                        response.status(500);
+                        response.text(err.to_string())
                     }
                 }
+            },
+            Err(RouteError::MethodNotAllowed(methods)) => {
+                response.set_header(String::from(ALLOW_HEADER), Self::get_allow_header(&methods));
+                response.status(405);
+                response.text(format!("Method {} Not Allowed: {:?}", request.method.as_str(), request.path))
+            },
+            Err(RouteError::NotFound(err)) => {
+                response.status(404);
+                response.text(err)
+            }
+        }
+    }
 
-                String::from(result)
+
+    // Find the first port of the range that is free on every address
+    fn bind_available_port(&self) -> Result<Vec<TcpListener>, ServerError> {
+        let addresses = self.config.get_addresses();
+
+        for port in self.config.port_range.clone() {
+            match Self::bind_addresses(&addresses, port) {
+                Ok(listeners) => return Ok(listeners),
+                Err(ServerError::AddressInUse(_)) => continue,
+                Err(err) => return Err(err)
+            }
+        }
+
+        Err(ServerError::NoPortAvailable(self.config.port_range.clone()))
+    }
+
+    fn bind_addresses(addresses: &[String], mut port: u16) -> Result<Vec<TcpListener>, ServerError> {
+        let mut listeners = Vec::new();
+
+        for address in addresses {
+            for socket_address in Self::resolve_address(address, port)? {
+                let listener = TcpListener::bind(socket_address)
+                    .map_err(|err| ServerError::from_bind_error(socket_address, err))?;
+
+                // Every address shares the port the OS picked for the first one
+                if port == 0 {
+                    if let Ok(local_address) = listener.local_addr() {
+                        port = local_address.port();
+                    }
+                }
+
+                listeners.push(listener);
             }
         }
+
+        Ok(listeners)
     }
-}
\ No newline at end of file
+
+    fn resolve_address(address: &str, port: u16) -> Result<Vec<SocketAddr>, ServerError> {
+        let host = address.trim().trim_start_matches('[').trim_end_matches(']');
+        let socket_addresses = (host, port).to_socket_addrs()
+            .map_err(|_| ServerError::InvalidAddress(address.to_string()))?;
+
+        let mut result = Vec::new();
+
+        for socket_address in socket_addresses {
+            if !result.contains(&socket_address) {
+                result.push(socket_address);
+            }
+        }
+
+        if result.is_empty() {
+            return Err(ServerError::InvalidAddress(address.to_string()));
+        }
+
+        Ok(result)
+    }
+
+    fn get_allow_header(methods: &[RouteMethod]) -> String {
+        methods.iter().map(|method| method.as_str()).collect::<Vec<&str>>().join(", ")
+    }
+}
+
+impl IdleConnection {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        Self::resume(BufReader::new(stream), 0)
+    }
+
+    // The connection is polled without blocking until its next request arrives
+    fn resume(reader: BufReader<TcpStream>, served: usize) -> io::Result<Self> {
+        reader.get_ref().set_nonblocking(true)?;
+
+        Ok(IdleConnection {
+            reader,
+            served,
+            since: Instant::now()
+        })
+    }
+
+    // Whether the next request started arriving, `None` once the connection is closed or idle for too long
+    fn is_ready(&self, timeout: Duration) -> Option<bool> {
+        let mut byte = [0; 1];
+
+        match self.reader.get_ref().peek(&mut byte) {
+            Ok(0) => None,
+            Ok(_) => Some(true),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock && self.since.elapsed() < timeout => Some(false),
+            Err(_) => None
+        }
+    }
+}